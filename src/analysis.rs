@@ -0,0 +1,202 @@
+use itertools::Itertools;
+use crate::chord::NotePos;
+use crate::pitch::{Interval, Note, PitchClass};
+use crate::symbol::ChordSymbol;
+use crate::tuning::Tuning;
+
+#[derive(Debug)]
+pub struct ChordTemplate {
+    // canonical suffix written after the root, e.g. "m7b5"
+    pub suffix: &'static str,
+    // semitones above the root
+    pub intervals: &'static [i32],
+    // tones that can be left out of a voicing without changing the name
+    pub optional: &'static [i32]
+}
+
+pub const CHORD_TEMPLATES: &[ChordTemplate] = &[
+    ChordTemplate { suffix: "", intervals: &[0, 4, 7], optional: &[7] },
+    ChordTemplate { suffix: "m", intervals: &[0, 3, 7], optional: &[7] },
+    ChordTemplate { suffix: "dim", intervals: &[0, 3, 6], optional: &[] },
+    ChordTemplate { suffix: "aug", intervals: &[0, 4, 8], optional: &[] },
+    ChordTemplate { suffix: "5", intervals: &[0, 7], optional: &[] },
+    ChordTemplate { suffix: "sus2", intervals: &[0, 2, 7], optional: &[7] },
+    ChordTemplate { suffix: "sus4", intervals: &[0, 5, 7], optional: &[7] },
+    ChordTemplate { suffix: "6", intervals: &[0, 4, 7, 9], optional: &[7] },
+    ChordTemplate { suffix: "m6", intervals: &[0, 3, 7, 9], optional: &[7] },
+    ChordTemplate { suffix: "69", intervals: &[0, 4, 7, 9, 2], optional: &[7] },
+    ChordTemplate { suffix: "add9", intervals: &[0, 4, 7, 2], optional: &[7] },
+    ChordTemplate { suffix: "madd9", intervals: &[0, 3, 7, 2], optional: &[7] },
+    ChordTemplate { suffix: "7", intervals: &[0, 4, 7, 10], optional: &[7] },
+    ChordTemplate { suffix: "maj7", intervals: &[0, 4, 7, 11], optional: &[7] },
+    ChordTemplate { suffix: "m7", intervals: &[0, 3, 7, 10], optional: &[7] },
+    ChordTemplate { suffix: "mMaj7", intervals: &[0, 3, 7, 11], optional: &[7] },
+    ChordTemplate { suffix: "m7b5", intervals: &[0, 3, 6, 10], optional: &[] },
+    ChordTemplate { suffix: "dim7", intervals: &[0, 3, 6, 9], optional: &[] },
+    ChordTemplate { suffix: "7sus4", intervals: &[0, 5, 7, 10], optional: &[7] },
+    ChordTemplate { suffix: "7#5", intervals: &[0, 4, 8, 10], optional: &[] },
+    ChordTemplate { suffix: "7b5", intervals: &[0, 4, 6, 10], optional: &[] },
+    ChordTemplate { suffix: "7b9", intervals: &[0, 4, 7, 10, 1], optional: &[7] },
+    ChordTemplate { suffix: "7#9", intervals: &[0, 4, 7, 10, 3], optional: &[7] },
+    ChordTemplate { suffix: "9", intervals: &[0, 4, 7, 10, 2], optional: &[7] },
+    ChordTemplate { suffix: "maj9", intervals: &[0, 4, 7, 11, 2], optional: &[7] },
+    ChordTemplate { suffix: "m9", intervals: &[0, 3, 7, 10, 2], optional: &[7] },
+    ChordTemplate { suffix: "11", intervals: &[0, 4, 7, 10, 2, 5], optional: &[4, 7, 2] },
+    ChordTemplate { suffix: "m11", intervals: &[0, 3, 7, 10, 2, 5], optional: &[7, 2] },
+    ChordTemplate { suffix: "13", intervals: &[0, 4, 7, 10, 2, 5, 9], optional: &[7, 2, 5] },
+    ChordTemplate { suffix: "maj13", intervals: &[0, 4, 7, 11, 2, 5, 9], optional: &[7, 2, 5] },
+    ChordTemplate { suffix: "m13", intervals: &[0, 3, 7, 10, 2, 5, 9], optional: &[7, 2, 5] },
+];
+
+impl ChordTemplate {
+    fn required(&self) -> impl Iterator<Item = &i32> {
+        self.intervals.iter().filter(|i| !self.optional.contains(i))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChordCandidate {
+    pub root: Note,
+    pub template: &'static ChordTemplate,
+    // lowest sounding note when it is not the root
+    pub bass: Option<Note>,
+    // 0 for root position, 1 for the third in the bass and so on, None if the bass is an extension
+    pub inversion: Option<usize>,
+    // chord tones the shape leaves out
+    pub omitted: Vec<Interval>,
+    pub score: i32
+}
+
+impl ChordCandidate {
+    pub fn name(&self) -> String {
+        match self.bass {
+            Some(bass) => format!("{}{}/{}", self.root, self.template.suffix, bass),
+            None => format!("{}{}", self.root, self.template.suffix)
+        }
+    }
+
+    pub fn description(&self) -> String {
+        let position = match self.inversion {
+            Some(0) => "root position".to_owned(),
+            Some(1) => "1st inversion".to_owned(),
+            Some(2) => "2nd inversion".to_owned(),
+            Some(3) => "3rd inversion".to_owned(),
+            Some(n) => format!("{}th inversion", n),
+            None => "slash chord".to_owned()
+        };
+        if self.omitted.is_empty() {
+            position
+        } else {
+            format!("{}, no {}", position, self.omitted.iter().map(|i| i.short_name()).join(" "))
+        }
+    }
+}
+
+// common chord-chart spelling of the black keys: C#, Eb, F#, Ab, Bb
+pub fn chord_root_spelling(pitch_class: PitchClass) -> Note {
+    pitch_class.spell(matches!(pitch_class.semitone(), 3 | 8 | 10))
+}
+
+// ranked names the fretted shape could have, best first
+pub fn analyze(notes: &[NotePos], tuning: &Tuning) -> Vec<ChordCandidate> {
+    let pitches = notes.iter()
+        .filter_map(|note| tuning.pitch_at(*note))
+        .sorted_by_key(|p| p.midi())
+        .collect::<Vec<_>>();
+    let bass = match pitches.first() {
+        Some(bass) => bass.pitch_class(),
+        None => return vec![]
+    };
+    let pitch_classes = pitches.iter().map(|p| p.pitch_class()).unique().collect::<Vec<_>>();
+
+    let mut candidates = vec![];
+    for root in pitch_classes.iter() {
+        let played = pitch_classes.iter().map(|pc| root.interval_to(*pc).semitones()).collect::<Vec<_>>();
+        for template in CHORD_TEMPLATES {
+            let fits = played.iter().all(|i| template.intervals.contains(i))
+                && template.required().all(|i| played.contains(i));
+            if !fits {
+                continue;
+            }
+
+            let omitted = template.intervals.iter()
+                .filter(|i| !played.contains(i))
+                .map(|i| Interval(*i))
+                .collect::<Vec<_>>();
+            let bass_interval = root.interval_to(bass).semitones();
+            // templates list the stacked triad and the sixth or seventh first,
+            // anything else in the bass makes it a plain slash chord
+            let inversion = template.intervals.iter()
+                .position(|i| *i == bass_interval)
+                .filter(|pos| *pos < 3 || (*pos == 3 && bass_interval >= 9));
+            let mut score = played.len() as i32 * 4 - omitted.len() as i32 * 2 - template.intervals.len() as i32;
+            if bass == *root {
+                score += 5;
+            }
+
+            candidates.push(ChordCandidate {
+                root: chord_root_spelling(*root),
+                template,
+                bass: if bass == *root { None } else { Some(chord_root_spelling(bass)) },
+                inversion,
+                omitted,
+                score
+            });
+        }
+    }
+
+    candidates.into_iter()
+        .sorted_by_key(|c| -c.score)
+        .unique_by(|c| c.name())
+        .collect()
+}
+
+// true if the written name describes the same chord as one of the candidates,
+// spelling differences like C#/Db or maj7/M7 don't count
+pub fn name_matches(name: &str, candidates: &[ChordCandidate]) -> bool {
+    let symbol = match ChordSymbol::parse(name) {
+        Ok(symbol) => symbol,
+        Err(_) => return false
+    };
+    let intervals = symbol.intervals().into_iter().sorted().collect::<Vec<_>>();
+
+    candidates.iter().any(|c| {
+        c.root.pitch_class() == symbol.root.pitch_class()
+            && c.bass.unwrap_or(c.root).pitch_class() == symbol.bass_tone()
+            && c.template.intervals.iter().copied().sorted().eq(intervals.iter().copied())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(notes: &[NotePos]) -> Vec<String> {
+        analyze(notes, &Tuning::standard()).iter().map(|c| c.name()).collect()
+    }
+
+    #[test]
+    fn default_shape_is_dmaj7() {
+        let notes = [(5, 5), (7, 4), (6, 3), (7, 2)];
+        let candidates = analyze(&notes, &Tuning::standard());
+        assert_eq!(candidates[0].name(), "Dmaj7");
+        assert_eq!(candidates[0].inversion, Some(0));
+        assert!(name_matches("Dmaj7", &candidates));
+        assert!(name_matches("DM7", &candidates));
+        assert!(!name_matches("Dm7", &candidates));
+        assert!(!name_matches("D7", &candidates));
+        assert!(!name_matches("not a chord", &candidates));
+    }
+
+    #[test]
+    fn inversions_and_omitted_tones() {
+        // open C with the E on the low string
+        let c_over_e = names(&[(0, 6), (3, 5), (2, 4), (0, 3), (1, 2), (0, 1)]);
+        assert_eq!(c_over_e[0], "C/E");
+        // G B F, the fifth of a seventh chord can be left out
+        let candidates = analyze(&[(3, 6), (2, 5), (3, 4)], &Tuning::standard());
+        assert_eq!(candidates[0].name(), "G7");
+        assert_eq!(candidates[0].description(), "root position, no 5");
+        assert!(analyze(&[], &Tuning::standard()).is_empty());
+    }
+}
@@ -0,0 +1,71 @@
+// Sound output. Playing needs the "audio" feature, which links the system audio library through
+// cpal; without it the player only reports that, and chords can still be saved as wav files.
+
+#[cfg(feature = "audio")]
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+#[derive(Default)]
+pub struct Player {
+    // kept open while the sound plays, replaced by the next one
+    #[cfg(feature = "audio")]
+    stream: Option<cpal::Stream>
+}
+
+impl Player {
+    // renders at the device's rate and starts playing, anything still playing is cut off
+    #[cfg(feature = "audio")]
+    pub fn play(&mut self, render: impl FnOnce(u32) -> Vec<f32>) -> Result<(), String> {
+        use cpal::SampleFormat;
+
+        self.stream = None;
+        let device = cpal::default_host().default_output_device().ok_or("no audio output device")?;
+        let config = device.default_output_config().map_err(|e| e.to_string())?;
+        let format = config.sample_format();
+        let samples = render(config.sample_rate().0);
+        let config = config.into();
+
+        // samples are converted to whatever the device takes
+        let stream = match format {
+            SampleFormat::F32 => output_stream::<f32>(&device, &config, samples),
+            SampleFormat::F64 => output_stream::<f64>(&device, &config, samples),
+            SampleFormat::I8 => output_stream::<i8>(&device, &config, samples),
+            SampleFormat::I16 => output_stream::<i16>(&device, &config, samples),
+            SampleFormat::I32 => output_stream::<i32>(&device, &config, samples),
+            SampleFormat::I64 => output_stream::<i64>(&device, &config, samples),
+            SampleFormat::U8 => output_stream::<u8>(&device, &config, samples),
+            SampleFormat::U16 => output_stream::<u16>(&device, &config, samples),
+            SampleFormat::U32 => output_stream::<u32>(&device, &config, samples),
+            SampleFormat::U64 => output_stream::<u64>(&device, &config, samples),
+            format => return Err(format!("unsupported sample format {}", format))
+        }.map_err(|e| e.to_string())?;
+        stream.play().map_err(|e| e.to_string())?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub fn play(&mut self, _render: impl FnOnce(u32) -> Vec<f32>) -> Result<(), String> {
+        Err("built without sound, enable the audio feature to play chords".to_owned())
+    }
+}
+
+#[cfg(feature = "audio")]
+fn output_stream<T>(device: &cpal::Device, config: &cpal::StreamConfig, samples: Vec<f32>) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>
+{
+    let channels = config.channels as usize;
+    let mut position = 0;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            // the same mono sample on every channel, silence after the end
+            for frame in data.chunks_mut(channels) {
+                frame.fill(T::from_sample(samples.get(position).copied().unwrap_or(0.0)));
+                position += 1;
+            }
+        },
+        |error| log::error!("audio output failed: {}", error),
+        None
+    )
+}
@@ -0,0 +1,269 @@
+// Searching the chords tab. Free text is matched loosely against chord names: case is ignored,
+// characters may be skipped and a leading note is read in both spellings, so "Db" finds "C#".
+// Structural terms can be mixed in:
+//
+//   root=D quality=maj7   built on D, with the tones of a maj7 chord in any spelling
+//   bass=F#               over F#, as a slash chord or an inversion
+//   contains notes F# A   every listed note is a chord tone, or sounds in one of its voicings
+//
+// Shapes are searched by clicking dots on a blank diagram.
+
+use itertools::Itertools;
+use crate::chord::{chord_pitches, NotePos};
+use crate::instrument::Instrument;
+use crate::models::Chord;
+use crate::pitch::{capitalized, Note, PitchClass};
+use crate::symbol::ChordSymbol;
+
+const EXACT: i32 = 1000;
+const PREFIX: i32 = 800;
+const INFIX: i32 = 600;
+const SUBSEQUENCE: i32 = 400;
+
+#[derive(Default)]
+pub struct ChordQuery {
+    words: Vec<String>,
+    root: Option<PitchClass>,
+    // sorted semitones above the root
+    quality: Option<Vec<i32>>,
+    bass: Option<PitchClass>,
+    notes: Vec<PitchClass>,
+    // terms that couldn't be read, they are left out of the search
+    pub errors: Vec<String>
+}
+
+impl ChordQuery {
+    pub fn parse(input: &str) -> ChordQuery {
+        let mut query = ChordQuery::default();
+        let mut tokens = input.split_whitespace().peekable();
+        while let Some(token) = tokens.next() {
+            if let Some((key, value)) = token.split_once('=') {
+                query.add_term(key, value);
+            } else if token.eq_ignore_ascii_case("contains") {
+                if tokens.peek().is_some_and(|t| t.eq_ignore_ascii_case("notes") || t.eq_ignore_ascii_case("note")) {
+                    tokens.next();
+                }
+                while let Some(note) = tokens.peek().and_then(|t| parse_notes(t)) {
+                    query.notes.extend(note);
+                    tokens.next();
+                }
+            } else {
+                query.words.push(token.to_owned());
+            }
+        }
+        query
+    }
+
+    fn add_term(&mut self, key: &str, value: &str) {
+        let parsed = match key.to_lowercase().as_str() {
+            "root" => parse_note(value).map(|note| self.root = Some(note)),
+            "bass" => parse_note(value).map(|note| self.bass = Some(note)),
+            "notes" => parse_notes(value).map(|notes| self.notes.extend(notes)),
+            "quality" => {
+                let suffix = match value.to_lowercase().as_str() {
+                    "major" | "maj" => "",
+                    "minor" | "min" => "m",
+                    _ => value
+                };
+                ChordSymbol::parse(&format!("C{}", suffix))
+                    .ok()
+                    .map(|symbol| self.quality = Some(symbol.intervals().into_iter().sorted().collect()))
+            }
+            _ => {
+                self.errors.push(format!("unknown term {}, use root, quality, bass or notes", key));
+                return;
+            }
+        };
+        if parsed.is_none() {
+            self.errors.push(format!("can't read {}={}", key, value));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty() && !self.is_structural()
+    }
+
+    // structural queries keep the list order, free text puts the best matches first
+    pub fn is_structural(&self) -> bool {
+        self.root.is_some() || self.quality.is_some() || self.bass.is_some() || !self.notes.is_empty()
+    }
+
+    // higher is better, None when the name doesn't match; voicings are the stored chords of the name
+    pub fn score(&self, name: &str, voicings: &[&Chord]) -> Option<i32> {
+        let symbol = ChordSymbol::parse(name).ok();
+        if self.root.is_some() || self.quality.is_some() || self.bass.is_some() {
+            let symbol = symbol.as_ref()?;
+            if self.root.is_some_and(|root| root != symbol.root.pitch_class())
+                || self.bass.is_some_and(|bass| bass != symbol.bass_tone())
+                || self.quality.as_ref().is_some_and(|q| !symbol.intervals().into_iter().sorted().eq(q.iter().copied())) {
+                return None;
+            }
+        }
+        if !self.notes.is_empty() {
+            let in_symbol = symbol.as_ref().is_some_and(|s| contains_all(&s.tones(), &self.notes));
+            let in_voicing = voicings.iter().any(|chord| {
+                let sounding = chord_pitches(&chord.notes, &chord.instrument.tuning, 0).iter().map(|p| p.pitch_class()).collect::<Vec<_>>();
+                contains_all(&sounding, &self.notes)
+            });
+            if !in_symbol && !in_voicing {
+                return None;
+            }
+        }
+        if self.words.is_empty() {
+            return Some(0);
+        }
+
+        let text = self.words.join(" ");
+        let equivalent = match (&symbol, ChordSymbol::parse(&capitalized(&text))) {
+            (Some(symbol), Ok(searched)) => symbol.is_equivalent(&searched),
+            _ => false
+        };
+        if equivalent {
+            return Some(EXACT);
+        }
+        spellings(&text).iter().filter_map(|text| fuzzy_score(name, text)).max()
+    }
+}
+
+fn contains_all(tones: &[PitchClass], wanted: &[PitchClass]) -> bool {
+    wanted.iter().all(|tone| tones.contains(tone))
+}
+
+fn parse_note(text: &str) -> Option<PitchClass> {
+    let text = capitalized(text);
+    match Note::parse_prefix(&text) {
+        Some((note, consumed)) if consumed == text.len() => Some(note.pitch_class()),
+        _ => None
+    }
+}
+
+// "F#,A" or a single note
+fn parse_notes(text: &str) -> Option<Vec<PitchClass>> {
+    text.split(',').filter(|t| !t.is_empty()).map(parse_note).collect::<Option<Vec<_>>>().filter(|notes| !notes.is_empty())
+}
+
+// the text as typed and with its leading note spelled the other way
+fn spellings(text: &str) -> Vec<String> {
+    let capital = capitalized(text);
+    match Note::parse_prefix(&capital) {
+        Some((note, consumed)) if note.enharmonic() != note => {
+            vec![text.to_owned(), format!("{}{}", note.enharmonic(), &capital[consumed..])]
+        }
+        _ => vec![text.to_owned()]
+    }
+}
+
+fn fuzzy_score(name: &str, text: &str) -> Option<i32> {
+    let name = name.to_lowercase();
+    let text = text.to_lowercase();
+    let length_penalty = name.chars().count() as i32 - text.chars().count() as i32;
+    if name == text {
+        Some(EXACT - 1)
+    } else if name.starts_with(&text) {
+        Some(PREFIX - length_penalty)
+    } else if let Some(position) = name.find(&text) {
+        Some(INFIX - position as i32 - length_penalty)
+    } else {
+        // every character in order, fewer skipped ones score higher
+        let mut name_chars = name.chars();
+        let mut skipped = 0;
+        for c in text.chars() {
+            loop {
+                match name_chars.next() {
+                    Some(n) if n == c => break,
+                    Some(_) => skipped += 1,
+                    None => return None
+                }
+            }
+        }
+        Some(SUBSEQUENCE - skipped * 10 - length_penalty)
+    }
+}
+
+// the blank diagram in the chords sidebar
+#[derive(Default)]
+pub struct ShapeSearch {
+    pub instrument: Instrument,
+    pub notes: Vec<NotePos>
+}
+
+impl ShapeSearch {
+    // stored chords for the same tuning that have every clicked dot, exact shapes first
+    pub fn matches<'a>(&self, chords: &'a [Chord]) -> Vec<(&'a Chord, bool)> {
+        if self.notes.is_empty() {
+            return vec![];
+        }
+        chords.iter()
+            .filter(|chord| chord.instrument.tuning.strings == self.instrument.tuning.strings)
+            .filter(|chord| self.notes.iter().all(|note| chord.notes.contains(note)))
+            .map(|chord| (chord, chord.notes.len() == self.notes.len()))
+            .sorted_by(|(a, a_exact), (b, b_exact)| b_exact.cmp(a_exact).then(a.name.cmp(&b.name)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // names matching the query, best first like the chords tab lists them
+    fn ranked(query: &str, names: &[&str]) -> Vec<String> {
+        let query = ChordQuery::parse(query);
+        names.iter()
+            .filter_map(|name| query.score(name, &[]).map(|score| (name.to_string(), score)))
+            .sorted_by_key(|(_, score)| -score)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    fn chord(name: &str, notes: Vec<NotePos>) -> Chord {
+        Chord { notes, ..Chord::empty(name.to_owned(), Instrument::guitar()) }
+    }
+
+    #[test]
+    fn enharmonic_spellings_match() {
+        let names = ["D", "Dbmaj7", "C#m7", "C#"];
+        assert_eq!(ranked("Db", &names), ["C#", "C#m7", "Dbmaj7"]);
+        assert_eq!(ranked("c#maj7", &names), ["Dbmaj7"]);
+        assert_eq!(ranked("DbM7", &names)[0], "Dbmaj7");
+    }
+
+    #[test]
+    fn fuzzy_matches_rank_below_exact_ones() {
+        let names = ["Cmaj7", "Cm7b5", "Am7b5", "Am7", "G"];
+        assert_eq!(ranked("am7", &names), ["Am7", "Am7b5"]);
+        assert_eq!(ranked("m7", &names), ["Am7", "Cm7b5", "Am7b5", "Cmaj7"]);
+        assert!(ranked("xyz", &names).is_empty());
+    }
+
+    #[test]
+    fn structural_terms_filter() {
+        let names = ["D", "Dmaj7", "DM7/F#", "D7", "Gmaj7"];
+        assert_eq!(ranked("root=D quality=maj7", &names), ["Dmaj7", "DM7/F#"]);
+        assert_eq!(ranked("bass=F#", &names), ["DM7/F#"]);
+        assert_eq!(ranked("contains notes F# C", &names), ["D7"]);
+        let query = ChordQuery::parse("root=H quality=maj7 key=C");
+        assert_eq!(query.errors.len(), 2);
+        assert!(query.is_structural());
+
+        // a stored voicing can add notes the name doesn't have
+        let d_add_e = chord("D", vec![(0, 4), (2, 3), (3, 2), (0, 1)]);
+        assert_eq!(ChordQuery::parse("notes=E").score("D", &[&d_add_e]), Some(0));
+        assert_eq!(ChordQuery::parse("notes=E").score("D", &[]), None);
+    }
+
+    #[test]
+    fn exact_shapes_come_first() {
+        let e = chord("E", vec![(0, 6), (2, 5), (2, 4), (1, 3), (0, 2), (0, 1)]);
+        let am = chord("Am", vec![(0, 5), (2, 4), (2, 3), (1, 2), (0, 1)]);
+        let e_partial = chord("E5", vec![(2, 4), (1, 3)]);
+        let mut ukulele = chord("A", vec![(2, 4), (1, 3)]);
+        ukulele.instrument = Instrument::ukulele();
+        let chords = [e.clone(), am.clone(), e_partial.clone(), ukulele];
+
+        let search = ShapeSearch { instrument: Instrument::guitar(), notes: vec![(2, 4), (1, 3)] };
+        let found = search.matches(&chords).into_iter().map(|(chord, exact)| (chord.name.clone(), exact)).collect::<Vec<_>>();
+        assert_eq!(found, [("E5".to_owned(), true), ("E".to_owned(), false)]);
+        assert!(ShapeSearch::default().matches(&chords).is_empty());
+    }
+}
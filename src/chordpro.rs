@@ -0,0 +1,243 @@
+// ChordPro import and export. Songs keep the chords-over-lyrics layout in their text,
+// ChordPro puts the same chords inline in brackets: "[G]Amazing [C]grace".
+// Directives the song has no field for ({start_of_chorus}, {comment: ...}) are kept as
+// lines of the text, so a song survives the round trip unchanged.
+// Under {capo: n} ChordPro chords are the shapes played, the song text keeps the sounding chords.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::format::file_name;
+use crate::models::Song;
+use crate::sheet::{chord_line_words, is_mark, render_chord_line, ChordEvent, Line, Mark};
+use crate::symbol::ChordSymbol;
+
+pub const EXTENSIONS: [&str; 4] = ["cho", "chordpro", "chopro", "pro"];
+
+fn directive(line: &str) -> Option<(&str, &str)> {
+    let inner = line.trim().strip_prefix('{')?.strip_suffix('}')?;
+    match inner.split_once(':') {
+        Some((name, value)) => Some((name.trim(), value.trim())),
+        None => Some((inner.trim(), ""))
+    }
+}
+
+pub fn import(source: &str) -> Song {
+    let mut song = Song::empty("".to_owned());
+    let mut lines = vec![];
+    for line in source.lines() {
+        match directive(line) {
+            Some(("title" | "t", value)) => song.name = value.to_owned(),
+            Some(("artist", value)) => song.artist = value.to_owned(),
+            Some(("key", value)) => song.key = value.parse().ok(),
+            Some(("capo", value)) => song.capo = value.parse().unwrap_or(0),
+            Some(_) => lines.push(line.to_owned()),
+            None => lines.extend(inline_to_chords_over_lyrics(line))
+        }
+    }
+    song.text = lines.join("\n");
+    if song.capo > 0 {
+        let mut sheet = song.sheet();
+        let key = song.key.or_else(|| sheet.guess_key().map(|key| key.transpose(song.capo)));
+        sheet.transpose(song.capo, key.is_some_and(|key| key.prefers_flats()));
+        song.text = sheet.to_text();
+    }
+    song
+}
+
+// splits "[G]Amazing [C]grace" into a chord line and a lyric line, marks like [N.C.] or [|]
+// go on the chord line too, other brackets stay in the lyrics
+fn inline_to_chords_over_lyrics(line: &str) -> Vec<String> {
+    let mut chords: Vec<ChordEvent> = vec![];
+    let mut marks: Vec<Mark> = vec![];
+    // column right after the last chord or mark
+    let mut anchored_end = None;
+    let mut lyric = String::new();
+    let mut rest = line;
+    while let Some(open) = rest.find('[') {
+        let close = match rest[open..].find(']') {
+            Some(close) => open + close,
+            None => break
+        };
+        lyric.push_str(&rest[..open]);
+        let inner = &rest[open + 1..close];
+        let symbol = ChordSymbol::parse(inner).ok();
+        if symbol.is_some() || is_mark(inner) {
+            // chords written back to back pad the lyric, so every chord keeps its anchor
+            if let Some(end) = anchored_end {
+                let column = lyric.chars().count();
+                if column < end + 1 {
+                    lyric.push_str(&" ".repeat(end + 1 - column));
+                }
+            }
+            let offset = lyric.chars().count();
+            let written = symbol.as_ref().map_or(inner.to_owned(), |symbol| symbol.to_string());
+            anchored_end = Some(offset + written.chars().count());
+            match symbol {
+                Some(symbol) => chords.push(ChordEvent { offset, symbol }),
+                None => marks.push(Mark { offset, text: inner.to_owned() })
+            }
+        } else {
+            lyric.push_str(&rest[open..=close]);
+        }
+        rest = &rest[close + 1..];
+    }
+    lyric.push_str(rest);
+
+    if chords.is_empty() && marks.is_empty() {
+        return vec![lyric];
+    }
+
+    let chord_line = render_chord_line(&chords, &marks);
+    let lyric = lyric.trim_end().to_owned();
+    if lyric.is_empty() {
+        vec![chord_line]
+    } else {
+        vec![chord_line, lyric]
+    }
+}
+
+pub fn export(song: &Song) -> String {
+    let mut lines = vec![format!("{{title: {}}}", song.name)];
+    if !song.artist.is_empty() {
+        lines.push(format!("{{artist: {}}}", song.artist));
+    }
+    if let Some(key) = song.key {
+        lines.push(format!("{{key: {}}}", key));
+    }
+    if song.capo > 0 {
+        lines.push(format!("{{capo: {}}}", song.capo));
+    }
+
+    let mut sheet = song.sheet();
+    if song.capo > 0 {
+        let shape_key = song.sheet_key(&sheet).map(|key| key.transpose(-song.capo));
+        sheet.transpose(-song.capo, shape_key.is_some_and(|key| key.prefers_flats()));
+    }
+    for section in sheet.sections {
+        lines.extend(section.header);
+        for line in section.lines {
+            match line {
+                Line::Lyric { chords, marks, lyric, .. } => lines.push(chords_over_lyrics_to_inline(&chords, &marks, &lyric.unwrap_or_default())),
+                Line::Blank => lines.push("".to_owned()),
+                Line::Directive(directive) => lines.push(directive)
+            }
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+fn chords_over_lyrics_to_inline(chords: &[ChordEvent], marks: &[Mark], lyric: &str) -> String {
+    let words = chord_line_words(chords, marks);
+    let mut chars = lyric.chars().collect::<Vec<_>>();
+    if let Some((last, _)) = words.last() {
+        if *last > chars.len() {
+            chars.resize(*last, ' ');
+        }
+    }
+
+    let mut result = chars[..words.first().map_or(chars.len(), |(offset, _)| *offset)].iter().collect::<String>();
+    for (idx, (offset, name)) in words.iter().enumerate() {
+        let end = words.get(idx + 1).map_or(chars.len(), |(next, _)| *next);
+        let text = &chars[*offset..end];
+        result.push_str(&format!("[{}]", name));
+        // the padding import puts between stacked chords is dropped again
+        let is_padding = idx + 1 < words.len()
+            && text.len() == name.chars().count() + 1
+            && text.iter().all(|c| *c == ' ');
+        if !is_padding {
+            result.extend(text);
+        }
+    }
+    result
+}
+
+pub fn is_chordpro_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+// a single file or every ChordPro file in a directory
+pub fn import_path(path: &Path) -> io::Result<Vec<Song>> {
+    let files = if path.is_dir() {
+        let mut files = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| is_chordpro_file(p))
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    files.iter().map(|file| {
+        let mut song = import(&fs::read_to_string(file)?);
+        if song.name.is_empty() {
+            song.name = file.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        }
+        Ok(song)
+    }).collect()
+}
+
+// exporting into a directory writes "<song name>.cho" there
+pub fn export_path(song: &Song, path: &Path) -> io::Result<PathBuf> {
+    let file = if path.is_dir() {
+        path.join(format!("{}.cho", file_name(&song.name)))
+    } else {
+        path.to_path_buf()
+    };
+    fs::write(&file, export(song))?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(line: &str) -> String {
+        let source = format!("{{title: la}}\n{}\n", line);
+        let exported = export(&import(&source));
+        exported.strip_prefix("{title: la}\n").unwrap().trim_end_matches('\n').to_owned()
+    }
+
+    #[test]
+    fn stacked_chords_keep_their_anchor() {
+        assert_eq!(import("[D][G]Tight chords").text, "D G\n  Tight chords");
+        assert_eq!(round_trip("[D][G]Tight chords"), "[D][G]Tight chords");
+        assert_eq!(round_trip("Ti[Am7][D/F#][G]ght"), "Ti[Am7][D/F#][G]ght");
+        assert_eq!(round_trip("[G]Amazing [C]grace"), "[G]Amazing [C]grace");
+    }
+
+    #[test]
+    fn chords_at_the_end_of_a_line() {
+        assert_eq!(round_trip("how sweet the [D]sound[G]"), "how sweet the [D]sound[G]");
+        assert_eq!(round_trip("that saved a wretch[Em][D]"), "that saved a wretch[Em][D]");
+    }
+
+    #[test]
+    fn chord_only_lines() {
+        assert_eq!(import("[G]   [C]   [D]").text, "G  C  D");
+        assert_eq!(round_trip("[G]   [C]   [D]"), "[G]   [C]   [D]");
+        assert_eq!(round_trip("[G][C][D]"), "[G][C][D]");
+    }
+
+    #[test]
+    fn marks_stay_on_the_chord_line() {
+        assert_eq!(import("[N.C.]Quiet [G]now [|]").text, "N.C.  G   |\nQuiet now");
+        assert_eq!(round_trip("[N.C.]Quiet [G]now [|]"), "[N.C.]Quiet [G]now [|]");
+        assert_eq!(round_trip("[|:][G][C][:|][x2]"), "[|:][G][C][:|][x2]");
+        assert_eq!(round_trip("[Refrain] la"), "[Refrain] la");
+    }
+
+    #[test]
+    fn chords_under_a_capo_are_shapes() {
+        let song = import("{title: la}\n{capo: 2}\n[G]Amazing [C]grace");
+        assert_eq!(song.text, "A       D\nAmazing grace");
+        assert_eq!(export(&song), "{title: la}\n{capo: 2}\n[G]Amazing [C]grace\n");
+
+        let song = import("{title: la}\n{capo: 1}\n[Am]Amazing [F]grace [E7]now");
+        assert_eq!(song.text, "Bbm     Gb    F7\nAmazing grace now");
+        assert_eq!(export(&song), "{title: la}\n{capo: 1}\n[Am]Amazing [F]grace [E7]now\n");
+    }
+}
@@ -0,0 +1,171 @@
+// Layout of a chord diagram in its own coordinates, (0, 0) is the top left corner.
+// It knows nothing about egui, the widget in chord.rs and the svg export both paint its shapes.
+
+use crate::chord::NotePos;
+use crate::instrument::Instrument;
+use crate::tuning::Tuning;
+
+// TODO: extract into configuration
+const STRING_SPACING: f32 = 23.0;
+const WIDTH: f32 = 220.0;
+const LEFT_PADDING: f32 = 10.0;
+const RIGHT_PADDING: f32 = 10.0;
+const TOP_PADDING: f32 = 15.0;
+const BOTTOM_PADDING: f32 = 30.0;
+
+const STRING_THICKNESS: f32 = 1.0;
+pub const FRET_THICKNESS: f32 = 1.0;
+const STRING_EXTRACTION_SPACE: f32 = 17.5;
+const CAPO_THICKNESS: f32 = 5.0;
+const NOTE_RADIUS: f32 = 10.0;
+const NOTE_FONT_SIZE: f32 = 12.0;
+const FRET_FONT_SIZE: f32 = 14.0;
+const CAPO_FONT_SIZE: f32 = 10.0;
+
+pub type Point = (f32, f32);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    Center,
+    // vertically centered, starting at the point
+    Left
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagramShape {
+    Line { from: Point, to: Point, thickness: f32 },
+    // filled with the background so strings don't show through
+    Circle { center: Point, radius: f32 },
+    Text { pos: Point, text: String, size: f32, anchor: Anchor }
+}
+
+pub struct ChordLayout {
+    pub width: f32,
+    pub height: f32,
+    pub capo: i32,
+    // absolute fret of the first column shown
+    pub min_fret: i32,
+    pub fret_distance: f32,
+    // in painting order
+    pub shapes: Vec<DiagramShape>
+}
+
+impl ChordLayout {
+    // notes are stored relative to the capo, fret 0 is a string played open behind the capo
+    pub fn new(notes: &[NotePos], instrument: &Instrument, capo: i32) -> ChordLayout {
+        let tuning = &instrument.tuning;
+        let string_number = instrument.string_count();
+        let height = TOP_PADDING + BOTTOM_PADDING + (string_number - 1) as f32 * STRING_SPACING;
+
+        // filter open strings
+        let frets: Vec<i32> = notes.iter().filter(|(fret, _)| *fret != 0).map(|(x, _)| *x + capo).collect();
+        let min_fret = frets.iter().min().copied().unwrap_or(capo + 1);
+        let max_fret = match frets.iter().max() {
+            Some(max_fret) if max_fret - min_fret >= 3 => *max_fret,
+            _ => min_fret + 2
+        };
+        let fret_amount = max_fret - min_fret + 2;
+        let fret_distance = (WIDTH - LEFT_PADDING - STRING_EXTRACTION_SPACE - RIGHT_PADDING) / (fret_amount - 1) as f32;
+        let x_padding = LEFT_PADDING + STRING_EXTRACTION_SPACE;
+
+        let mut shapes = vec![];
+        for f in 1..(fret_amount + 1) {
+            let x = x_padding + (f - 1) as f32 * fret_distance;
+            shapes.push(DiagramShape::Line { from: (x, TOP_PADDING), to: (x, height - BOTTOM_PADDING), thickness: FRET_THICKNESS });
+            // the last fret line closes the diagram and has no column to label
+            if f < fret_amount {
+                shapes.push(DiagramShape::Text {
+                    pos: (x + fret_distance / 2.0, height - 7.5),
+                    text: fret_string_from_number(f + min_fret - 1),
+                    size: FRET_FONT_SIZE,
+                    anchor: Anchor::Center
+                });
+            }
+        }
+
+        for s in 1..(string_number + 1) {
+            let y = (s - 1) as f32 * STRING_SPACING + TOP_PADDING;
+
+            // muted and open strings are marked before the nut
+            let marker = (LEFT_PADDING + 2.5, y);
+            if !notes.iter().any(|(_, string)| *string == s) {
+                shapes.extend(muted_marker(marker));
+            }
+            if notes.iter().any(|(fret, string)| *fret == 0 && *string == s) {
+                shapes.extend(note_marker(marker, (capo, s), tuning));
+            }
+
+            shapes.push(DiagramShape::Line { from: (x_padding, y), to: (WIDTH - RIGHT_PADDING, y), thickness: STRING_THICKNESS });
+
+            // pressed notes sit in the middle of their fret column
+            for (fret, _) in notes.iter().filter(|(fret, string)| *fret != 0 && *string == s) {
+                let x = x_padding + (fret + capo - min_fret) as f32 * fret_distance;
+                shapes.extend(note_marker((x + fret_distance / 2.0, y), (fret + capo, s), tuning));
+            }
+        }
+
+        // capo, as a bar in place of the nut when it is right before the shown frets
+        if capo > 0 {
+            if min_fret == capo + 1 {
+                shapes.push(DiagramShape::Line {
+                    from: (x_padding, TOP_PADDING - 5.0),
+                    to: (x_padding, height - BOTTOM_PADDING + 5.0),
+                    thickness: CAPO_THICKNESS
+                });
+            }
+            shapes.push(DiagramShape::Text {
+                pos: (x_padding + CAPO_THICKNESS, TOP_PADDING / 2.0 - 1.0),
+                text: format!("capo {}", fret_string_from_number(capo)),
+                size: CAPO_FONT_SIZE,
+                anchor: Anchor::Left
+            });
+        }
+
+        ChordLayout { width: WIDTH, height, capo, min_fret, fret_distance, shapes }
+    }
+
+    // note under a point of the diagram, the fret is relative to the capo like stored notes;
+    // the string is the nearest one, points left of the nut give the open string
+    pub fn note_at(&self, pos: Point) -> NotePos {
+        let (x, y) = pos;
+        let string = ((y - TOP_PADDING) / STRING_SPACING).round() as i32 + 1;
+        let x_padding = LEFT_PADDING + STRING_EXTRACTION_SPACE;
+        let fret = if x > x_padding {
+            ((x - x_padding) / self.fret_distance).floor() as i32 + self.min_fret - self.capo
+        } else {
+            0
+        };
+        (fret, string)
+    }
+}
+
+fn note_marker(center: Point, note: NotePos, tuning: &Tuning) -> Vec<DiagramShape> {
+    vec![
+        DiagramShape::Circle { center, radius: NOTE_RADIUS },
+        DiagramShape::Text { pos: center, text: get_note_by_string_and_fret(note, tuning), size: NOTE_FONT_SIZE, anchor: Anchor::Center }
+    ]
+}
+
+fn muted_marker((x, y): Point) -> Vec<DiagramShape> {
+    vec![
+        DiagramShape::Line { from: (x - 5.0, y - 5.0), to: (x + 5.0, y + 5.0), thickness: 1.0 },
+        DiagramShape::Line { from: (x + 5.0, y - 5.0), to: (x - 5.0, y + 5.0), thickness: 1.0 }
+    ]
+}
+
+fn get_note_by_string_and_fret(note: NotePos, tuning: &Tuning) -> String {
+    tuning.pitch_at(note).map(|p| p.note.to_string()).unwrap_or_default()
+}
+
+fn fret_string_from_number(i: i32) -> String {
+    let numerals = [(10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I")];
+    let mut rest = i;
+    let mut result = String::new();
+    for (value, numeral) in numerals {
+        while rest >= value {
+            result.push_str(numeral);
+            rest -= value;
+        }
+    }
+    result
+}
@@ -0,0 +1,17 @@
+// Formatting shared by everything that writes files: names of the files themselves
+// and the numbers in svg and pdf output.
+
+// song and chord names can hold anything, file names keep letters, digits and a few separators
+pub fn file_name(name: &str) -> String {
+    let name = name.trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '\'' | ',' | '#' | '+') { c } else { '_' })
+        .collect::<String>();
+    if name.is_empty() { "untitled".to_owned() } else { name }
+}
+
+// two decimals at most, without trailing zeros, so the output stays stable and short
+pub fn number(value: f32) -> String {
+    let formatted = format!("{:.2}", value);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
@@ -0,0 +1,133 @@
+// Undo and redo. Every undoable Msg is recorded together with the Msg that reverts it,
+// worked out from the state right before it ran, so undoing is just running another message.
+
+use std::time::{Duration, Instant};
+use crate::state::Msg;
+
+const MAX_ENTRIES: usize = 200;
+// typing or dragging a value makes a message per frame, quick successive ones become one step
+const MERGE_WINDOW: Duration = Duration::from_millis(1500);
+
+pub struct HistoryEntry {
+    pub label: String,
+    pub forward: Msg,
+    pub inverse: Msg,
+    at: Instant
+}
+
+#[derive(Default)]
+pub struct History {
+    // oldest first, the last one is undone next
+    pub done: Vec<HistoryEntry>,
+    // the last one is redone next
+    pub undone: Vec<HistoryEntry>
+}
+
+impl History {
+    pub fn record(&mut self, label: String, forward: Msg, inverse: Msg) {
+        self.undone.clear();
+        if let Some(last) = self.done.last_mut() {
+            if forward.merges_with(&last.forward) && last.at.elapsed() < MERGE_WINDOW {
+                // the oldest inverse is kept, it reverts the whole run of edits
+                last.forward = forward;
+                last.at = Instant::now();
+                return;
+            }
+        }
+        self.done.push(HistoryEntry { label, forward, inverse, at: Instant::now() });
+        if self.done.len() > MAX_ENTRIES {
+            self.done.remove(0);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Chord;
+    use crate::state::{run_messages, State};
+
+    fn snapshot(state: &State) -> String {
+        serde_json::to_string(&(&state.chords, &state.songs, &state.trash)).unwrap()
+    }
+
+    // undoing puts the state back as it was, redoing as it was after the message
+    fn round_trip(state: &mut State, message: Msg) {
+        let before = snapshot(state);
+        run_messages(state, &[message]);
+        let after = snapshot(state);
+        assert_ne!(before, after);
+        run_messages(state, &[Msg::Undo]);
+        assert_eq!(snapshot(state), before);
+        run_messages(state, &[Msg::Redo]);
+        assert_eq!(snapshot(state), after);
+    }
+
+    #[test]
+    fn undo_reverts_every_edit() {
+        let mut state = State::default();
+        let chord = state.chords[0].clone();
+        let song_id = state.songs[0].id;
+
+        let mut clicked = chord.notes.clone();
+        clicked.push((0, 1));
+        round_trip(&mut state, Msg::SetChordNotes(chord.id, clicked));
+        let shifted = state.chords[0].notes.iter().map(|(fret, string)| (fret + 1, *string)).collect();
+        round_trip(&mut state, Msg::SetChordNotes(chord.id, shifted));
+        round_trip(&mut state, Msg::SetSongText(song_id, "Am\nla".to_owned()));
+        round_trip(&mut state, Msg::RenameSong(song_id, "Other".to_owned()));
+        round_trip(&mut state, Msg::UpdateChord(Chord { name: "D6".to_owned(), ..chord.clone() }));
+        // redone, so the chord is in the trash
+        round_trip(&mut state, Msg::DeleteChord(chord.id));
+        round_trip(&mut state, Msg::RestoreFromTrash(chord.id));
+        round_trip(&mut state, Msg::DeleteSong(song_id));
+    }
+
+    #[test]
+    fn typing_is_one_step() {
+        let mut state = State::default();
+        let id = state.songs[0].id;
+        let original = state.songs[0].text.clone();
+        run_messages(&mut state, &[Msg::SetSongText(id, "a".to_owned()), Msg::SetSongText(id, "ab".to_owned())]);
+        run_messages(&mut state, &[Msg::SetSongText(id, "abc".to_owned())]);
+        assert_eq!(state.history.done.len(), 1);
+
+        // a pause starts a new step
+        state.history.done[0].at -= MERGE_WINDOW;
+        run_messages(&mut state, &[Msg::SetSongText(id, "abcd".to_owned())]);
+        assert_eq!(state.history.done.len(), 2);
+        run_messages(&mut state, &[Msg::Undo]);
+        assert_eq!(state.songs[0].text, "abc");
+        run_messages(&mut state, &[Msg::Undo]);
+        assert_eq!(state.songs[0].text, original);
+        run_messages(&mut state, &[Msg::Redo]);
+        assert_eq!(state.songs[0].text, "abc");
+    }
+
+    #[test]
+    fn a_new_edit_clears_redo() {
+        let mut state = State::default();
+        let id = state.songs[0].id;
+        run_messages(&mut state, &[Msg::SetSongText(id, "a".to_owned()), Msg::Undo]);
+        assert_eq!(state.history.undone.len(), 1);
+        run_messages(&mut state, &[Msg::RenameSong(id, "Other".to_owned())]);
+        assert!(state.history.undone.is_empty());
+        run_messages(&mut state, &[Msg::Redo]);
+        assert_eq!(state.songs[0].text, State::default().songs[0].text);
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let mut history = History::default();
+        for idx in 0..MAX_ENTRIES + 10 {
+            history.record(idx.to_string(), Msg::SelectChord(idx.to_string()), Msg::SelectChord("".to_owned()));
+        }
+        assert_eq!(history.done.len(), MAX_ENTRIES);
+        assert_eq!(history.done[0].label, "10");
+    }
+}
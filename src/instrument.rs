@@ -0,0 +1,90 @@
+use std::hash::Hash;
+use eframe::egui::*;
+use serde::{Serialize, Deserialize};
+use crate::chord::NotePos;
+use crate::tuning::{Tuning, tuning_editor};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Instrument {
+    pub name: String,
+    pub tuning: Tuning,
+    // highest fret a note can be placed on
+    pub frets: i32
+}
+
+impl Default for Instrument {
+    fn default() -> Self {
+        Instrument::guitar()
+    }
+}
+
+impl Instrument {
+    fn preset(name: &str, tuning: Tuning, frets: i32) -> Self {
+        Instrument {
+            name: name.to_owned(),
+            tuning,
+            frets
+        }
+    }
+
+    pub fn guitar() -> Self {
+        Instrument::preset("Guitar", Tuning::standard(), 22)
+    }
+
+    pub fn seven_string_guitar() -> Self {
+        Instrument::preset("7-string guitar", Tuning::seven_string(), 24)
+    }
+
+    pub fn eight_string_guitar() -> Self {
+        Instrument::preset("8-string guitar", Tuning::eight_string(), 24)
+    }
+
+    pub fn bass() -> Self {
+        Instrument::preset("Bass", Tuning::bass(), 20)
+    }
+
+    pub fn ukulele() -> Self {
+        Instrument::preset("Ukulele", Tuning::ukulele(), 15)
+    }
+
+    pub fn presets() -> Vec<Instrument> {
+        vec![
+            Instrument::guitar(),
+            Instrument::seven_string_guitar(),
+            Instrument::eight_string_guitar(),
+            Instrument::bass(),
+            Instrument::ukulele()
+        ]
+    }
+
+    pub fn string_count(&self) -> i32 {
+        self.tuning.string_count()
+    }
+
+    pub fn contains(&self, note: NotePos) -> bool {
+        note.1 >= 1 && note.1 <= self.string_count() && note.0 >= 0 && note.0 <= self.frets
+    }
+}
+
+// instrument picker with its tuning and fret range, returns true if anything was changed
+pub fn instrument_editor(ui: &mut Ui, id_source: impl Hash, instrument: &mut Instrument) -> bool {
+    let mut changed = false;
+    let id = ui.make_persistent_id(id_source);
+
+    ui.horizontal(|ui| {
+        ComboBox::from_id_source(id)
+            .selected_text(&instrument.name)
+            .show_ui(ui, |ui| {
+                for preset in Instrument::presets() {
+                    if ui.selectable_label(instrument.name == preset.name, &preset.name).clicked() {
+                        *instrument = preset;
+                        changed = true;
+                    }
+                }
+            });
+        changed |= ui.add(DragValue::new(&mut instrument.frets).clamp_range(1..=36).suffix(" frets")).changed();
+    });
+    changed |= tuning_editor(ui, id.with("tuning"), &mut instrument.tuning);
+
+    changed
+}
@@ -0,0 +1,311 @@
+// A library is a directory that can be shared, diffed and backed up:
+//
+//   <library>/chords.json      every chord shape
+//   <library>/songs/<id>.json  one file per song, named by id so renames and reordering keep the file
+//
+// A library is only started in a directory by an explicit create, opening a directory without
+// these files is an error. The app keeps the files in sync with its state and polls them for
+// changes made outside; those are read in unless the app has unsaved edits too, then the user
+// picks which side to keep.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use crate::models::{fix_duplicate_ids, Chord, Song};
+
+const CHORDS_FILE: &str = "chords.json";
+const SONGS_DIR: &str = "songs";
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub enum PollEvent {
+    Reloaded,
+    // files changed on disk while the app had unsaved edits, nothing is read or written until resolved
+    Conflict,
+    Failed(String)
+}
+
+pub struct Library {
+    pub path: PathBuf,
+    // file contents as last read or written, only files that differ get written again
+    known: BTreeMap<PathBuf, String>,
+    // modification times seen after the last read or write, anything else is an outside edit
+    stamps: BTreeMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+    pub conflict: bool
+}
+
+impl Library {
+    fn new(path: &Path) -> Library {
+        Library {
+            path: path.to_path_buf(),
+            known: BTreeMap::new(),
+            stamps: BTreeMap::new(),
+            last_poll: Instant::now(),
+            conflict: false
+        }
+    }
+
+    // the chords and songs are replaced by what the library holds
+    pub fn open(path: &Path, chords: &mut Vec<Chord>, songs: &mut Vec<Song>) -> io::Result<Library> {
+        if !is_library(path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no library here, create one to start it"));
+        }
+        let mut library = Library::new(path);
+        (*chords, *songs) = library.read()?;
+        Ok(library)
+    }
+
+    // starts a new library holding the chords and songs, the directory is made if it is missing
+    pub fn create(path: &Path, chords: &[Chord], songs: &[Song]) -> io::Result<Library> {
+        if is_library(path) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "there is a library here already, open it"));
+        }
+        let mut library = Library::new(path);
+        library.write(chords, songs)?;
+        Ok(library)
+    }
+
+    // reloads after outside edits and writes local changes, at most once per interval
+    pub fn poll(&mut self, chords: &mut Vec<Chord>, songs: &mut Vec<Song>) -> Option<PollEvent> {
+        if self.conflict || self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let changed_outside = match self.scan() {
+            Ok(stamps) => stamps != self.stamps,
+            Err(error) => return Some(PollEvent::Failed(format!("can't read library: {}", error)))
+        };
+        if changed_outside {
+            let changed_inside = match library_files(&self.path, chords, songs) {
+                Ok(files) => files != self.known,
+                Err(error) => return Some(PollEvent::Failed(format!("can't save library: {}", error)))
+            };
+            if changed_inside {
+                self.conflict = true;
+                return Some(PollEvent::Conflict);
+            }
+            return match self.reload() {
+                Ok(loaded) => {
+                    (*chords, *songs) = loaded;
+                    Some(PollEvent::Reloaded)
+                }
+                Err(error) => Some(PollEvent::Failed(format!("can't reload library: {}", error)))
+            };
+        }
+        self.write(chords, songs).err().map(|error| PollEvent::Failed(format!("can't save library: {}", error)))
+    }
+
+    // resolves a conflict by dropping the edits in memory
+    pub fn reload(&mut self) -> io::Result<(Vec<Chord>, Vec<Song>)> {
+        let loaded = self.read()?;
+        self.conflict = false;
+        Ok(loaded)
+    }
+
+    // resolves a conflict by writing over the edits on disk, song files added there are removed
+    pub fn overwrite(&mut self, chords: &[Chord], songs: &[Song]) -> io::Result<()> {
+        for file in song_files(&self.path)? {
+            self.known.entry(file).or_default();
+        }
+        self.conflict = false;
+        self.write(chords, songs)
+    }
+
+    pub fn write(&mut self, chords: &[Chord], songs: &[Song]) -> io::Result<()> {
+        if self.conflict {
+            return Err(io::Error::other("the files changed on disk too, reload them or keep the app's version"));
+        }
+        let files = library_files(&self.path, chords, songs)?;
+        fs::create_dir_all(self.path.join(SONGS_DIR))?;
+        for (file, content) in files.iter() {
+            if self.known.get(file) != Some(content) {
+                fs::write(file, content)?;
+            }
+        }
+        // only files this library wrote or read are removed, nothing else in the directory
+        for file in self.known.keys().filter(|file| !files.contains_key(*file)) {
+            if file.exists() {
+                fs::remove_file(file)?;
+            }
+        }
+        self.known = files;
+        self.stamps = self.scan()?;
+        Ok(())
+    }
+
+    fn read(&mut self) -> io::Result<(Vec<Chord>, Vec<Song>)> {
+        let mut known = BTreeMap::new();
+        let chords_file = self.path.join(CHORDS_FILE);
+        let mut chords = if chords_file.exists() {
+            let content = fs::read_to_string(&chords_file)?;
+            let chords = serde_json::from_str(&content).map_err(|e| invalid_data(&chords_file, e))?;
+            known.insert(chords_file, content);
+            chords
+        } else {
+            vec![]
+        };
+
+        let mut songs = vec![];
+        let mut missing_ids = false;
+        for file in song_files(&self.path)? {
+            let content = fs::read_to_string(&file)?;
+            let song: serde_json::Value = serde_json::from_str(&content).map_err(|e| invalid_data(&file, e))?;
+            missing_ids |= song.get("id").is_none();
+            songs.push(serde_json::from_value(song).map_err(|e| invalid_data(&file, e))?);
+            known.insert(file, content);
+        }
+
+        let changed_ids = fix_duplicate_ids(&mut chords, &mut songs);
+        self.known = known;
+        self.stamps = self.scan()?;
+        // ids given while reading are written back, otherwise every read would give new ones
+        if missing_ids || changed_ids {
+            self.write(&chords, &songs)?;
+        }
+        Ok((chords, songs))
+    }
+
+    fn scan(&self) -> io::Result<BTreeMap<PathBuf, SystemTime>> {
+        let mut stamps = BTreeMap::new();
+        let chords_file = self.path.join(CHORDS_FILE);
+        if chords_file.exists() {
+            stamps.insert(chords_file.clone(), fs::metadata(&chords_file)?.modified()?);
+        }
+        for file in song_files(&self.path)? {
+            let modified = fs::metadata(&file)?.modified()?;
+            stamps.insert(file, modified);
+        }
+        Ok(stamps)
+    }
+}
+
+fn is_library(path: &Path) -> bool {
+    path.join(CHORDS_FILE).is_file() || path.join(SONGS_DIR).is_dir()
+}
+
+fn song_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let songs_dir = path.join(SONGS_DIR);
+    if !songs_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut files = fs::read_dir(songs_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+fn library_files(path: &Path, chords: &[Chord], songs: &[Song]) -> io::Result<BTreeMap<PathBuf, String>> {
+    let mut files = BTreeMap::new();
+    files.insert(path.join(CHORDS_FILE), serde_json::to_string_pretty(chords)?);
+
+    for song in songs {
+        files.insert(path.join(SONGS_DIR).join(format!("{}.json", song.id)), serde_json::to_string_pretty(song)?);
+    }
+    Ok(files)
+}
+
+fn invalid_data(file: &Path, error: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file.display(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use itertools::Itertools;
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        env::temp_dir().join(format!("rusty-guitar-library-{}", crate::models::new_id()))
+    }
+
+    fn file_names(path: &Path) -> Vec<String> {
+        song_files(path).unwrap().iter().map(|file| file.file_name().unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn libraries_are_only_started_on_request() {
+        let path = temp_dir();
+        fs::create_dir_all(&path).unwrap();
+        let (mut chords, mut songs) = (vec![], vec![Song::empty("la".to_owned())]);
+        assert!(Library::open(&path, &mut chords, &mut songs).is_err());
+        assert_eq!(fs::read_dir(&path).unwrap().count(), 0);
+        assert_eq!(songs.len(), 1);
+
+        Library::create(&path, &chords, &songs).unwrap();
+        assert!(Library::create(&path, &chords, &songs).is_err());
+        let (mut chords, mut songs) = (vec![], vec![]);
+        Library::open(&path, &mut chords, &mut songs).unwrap();
+        assert_eq!(songs[0].name, "la");
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn song_files_are_named_by_id() {
+        let path = temp_dir();
+        let mut songs = vec![Song::empty("la".to_owned()), Song::empty("la".to_owned())];
+        let mut library = Library::create(&path, &[], &songs).unwrap();
+        let names = songs.iter().map(|song| format!("{}.json", song.id)).sorted().collect::<Vec<_>>();
+        assert_eq!(file_names(&path), names);
+
+        songs.reverse();
+        songs[0].name = "renamed".to_owned();
+        library.write(&[], &songs).unwrap();
+        assert_eq!(file_names(&path), names);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn edits_on_disk_and_in_memory_conflict() {
+        let path = temp_dir();
+        let (mut chords, mut songs) = (vec![], vec![Song::empty("la".to_owned())]);
+        let mut library = Library::create(&path, &chords, &songs).unwrap();
+        let outside = Song::empty("outside".to_owned());
+        let outside_file = path.join(SONGS_DIR).join(format!("{}.json", outside.id));
+        fs::write(&outside_file, serde_json::to_string(&outside).unwrap()).unwrap();
+        songs[0].name = "inside".to_owned();
+
+        library.last_poll -= POLL_INTERVAL;
+        assert!(matches!(library.poll(&mut chords, &mut songs), Some(PollEvent::Conflict)));
+        assert_eq!(songs.len(), 1);
+        library.last_poll -= POLL_INTERVAL;
+        assert!(library.poll(&mut chords, &mut songs).is_none());
+        assert!(library.write(&chords, &songs).is_err());
+
+        library.overwrite(&chords, &songs).unwrap();
+        assert!(!outside_file.exists());
+        let (_, reloaded) = library.reload().unwrap();
+        assert_eq!(reloaded.iter().map(|song| song.name.as_str()).collect::<Vec<_>>(), ["inside"]);
+
+        // without edits in memory the disk is read in
+        fs::write(&outside_file, serde_json::to_string(&outside).unwrap()).unwrap();
+        library.last_poll -= POLL_INTERVAL;
+        assert!(matches!(library.poll(&mut chords, &mut songs), Some(PollEvent::Reloaded)));
+        assert_eq!(songs.len(), 2);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn songs_without_ids_keep_the_one_they_get() {
+        let path = temp_dir();
+        fs::create_dir_all(path.join(SONGS_DIR)).unwrap();
+        let mut song = serde_json::to_value(Song::empty("old".to_owned())).unwrap();
+        song.as_object_mut().unwrap().remove("id");
+        fs::write(path.join(SONGS_DIR).join("old.json"), song.to_string()).unwrap();
+
+        let (mut chords, mut songs) = (vec![], vec![]);
+        Library::open(&path, &mut chords, &mut songs).unwrap();
+        let id = songs[0].id;
+        let (mut chords, mut songs) = (vec![], vec![]);
+        let mut library = Library::open(&path, &mut chords, &mut songs).unwrap();
+        assert_eq!(songs[0].id, id);
+        // nothing changed outside, so nothing is read again
+        library.last_poll -= POLL_INTERVAL;
+        assert!(library.poll(&mut chords, &mut songs).is_none());
+        fs::remove_dir_all(path).unwrap();
+    }
+}
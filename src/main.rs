@@ -1,22 +1,61 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+mod analysis;
+mod audio;
 mod chord;
+mod chord_search;
+mod chordpro;
+mod diagram;
+mod format;
+mod history;
+mod instrument;
+mod library;
+mod midi;
+mod midi_input;
 mod models;
+mod neck;
+mod pdf;
+mod persistence;
+mod pitch;
+mod scale;
+mod sheet;
+mod song_search;
+mod songbook;
 mod state;
+mod svg;
+mod symbol;
+mod synth;
+mod tuning;
+mod voicing;
 
-use std::collections::HashSet;
-use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
 use eframe::{Frame, Storage};
 use eframe::egui::*;
 use eframe::egui::panel::Side;
 use env_logger::Builder;
-use crate::chord::{draw_chord};
+use crate::analysis::{analyze, name_matches};
+use crate::chord::{draw_chord, pitches_label};
+use crate::chord_search::ChordQuery;
+use crate::song_search::{SongMatch, SongQuery};
 use itertools::Itertools;
-use log::{debug, LevelFilter};
-use crate::models::{Chord, Note};
-use crate::state::{Msg, run_messages, State, Tab};
+use log::LevelFilter;
+use crate::models::{song_name_taken, Chord, MAX_DIFFICULTY};
+use crate::midi_input::MidiInput;
+use crate::neck::{draw_neck, CagedShape, Labels, Overlay};
+use crate::persistence::load_state;
+use crate::pitch::{Pitch, PitchClass};
+use crate::scale::Scale;
+use crate::state::{ChordSort, Msg, run_messages, State, Tab};
+use crate::svg::{chord_svg, SvgStyle};
+use crate::symbol::ChordSymbol;
+use crate::synth::{render_chord, PlayStyle};
+use crate::instrument::{Instrument, instrument_editor};
+use crate::library::PollEvent;
 
 const STORAGE_KEY: &str = "state";
+const SONG_NAME_FIELD: &str = "song_name";
+const SONG_TEXT_FIELD: &str = "song_text";
 
 fn main() -> Result<(), eframe::Error> {
     Builder::from_default_env().filter_level(LevelFilter::Debug).init(); // Log to stderr (if you run with `RUST_LOG=debug`).
@@ -31,11 +70,11 @@ fn main() -> Result<(), eframe::Error> {
         "MyGuitar",
         options,
         Box::new(|_cc| {
-             _cc.storage
-                 .map(|s| s.get_string(STORAGE_KEY).unwrap_or("{}".to_owned()))
-                 .map(|str| serde_json::from_str::<State>(&str).unwrap_or(State::default()))
-                 .map(|s| Box::new(s))
-                 .unwrap()
+             let mut state = load_state(_cc.storage.and_then(|s| s.get_string(STORAGE_KEY)));
+             if let Some(path) = state.library_path.clone() {
+                 run_messages(&mut state, &[Msg::OpenLibrary(path)]);
+             }
+             Box::new(state)
         }),
     )
 }
@@ -44,13 +83,63 @@ impl eframe::App for State {
     fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
         let mut messages: Vec<Msg> = vec![];
 
+        if let Some(library) = self.library.as_mut() {
+            match library.poll(&mut self.chords, &mut self.songs) {
+                Some(PollEvent::Reloaded) => {
+                    // recorded steps point at what was replaced
+                    self.history.clear();
+                    self.song_index = None;
+                    self.library_status = "reloaded after changes on disk".to_owned();
+                }
+                Some(PollEvent::Conflict) => {
+                    self.library_window_open = true;
+                    self.library_status = "the library changed on disk while the app had unsaved edits".to_owned();
+                }
+                Some(PollEvent::Failed(error)) => self.library_status = error,
+                None => ()
+            }
+            // keep polling while the window is idle
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+
+        // taken before any text field sees them, the history also covers text edits
+        if ctx.input_mut(|i| i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z))) {
+            messages.push(Msg::Redo);
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Z))) {
+            messages.push(Msg::Undo);
+        }
+
         TopBottomPanel::top("tabs").show_separator_line(true).exact_height(30.0).show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.selectable_value(&mut self.selected_tab, Tab::Chords, "Chords");
                 ui.selectable_value(&mut self.selected_tab, Tab::Songs, "Songs");
+                ui.separator();
+                ui.toggle_value(&mut self.library_window_open, "Library");
+                ui.toggle_value(&mut self.history_window_open, "History");
+                ui.toggle_value(&mut self.trash_window_open, format!("Trash ({})", self.trash.len()));
             });
         });
 
+        if self.library_window_open {
+            library_window(self, &mut messages, ctx);
+        }
+        if self.history_window_open {
+            history_window(self, &mut messages, ctx);
+        }
+        if self.trash_window_open {
+            trash_window(self, &mut messages, ctx);
+        }
+        if self.chord_to_delete.is_some() {
+            delete_chord_window(self, &mut messages, ctx);
+        }
+        if self.song_to_delete.is_some() {
+            delete_song_window(self, &mut messages, ctx);
+        }
+        if self.load_error.is_some() {
+            load_error_window(self, &mut messages, ctx);
+        }
+
         match self.selected_tab {
             Tab::Chords => chords_section(self, &mut messages, ctx),
             Tab::Songs => songs_section(self, &mut messages, ctx)
@@ -60,183 +149,981 @@ impl eframe::App for State {
     }
 
     fn save(&mut self, _storage: &mut dyn Storage) {
+        if self.load_error.is_some() {
+            return;
+        }
+        if let Some(library) = self.library.as_mut() {
+            if let Err(error) = library.write(&self.chords, &self.songs) {
+                log::error!("can't save library {}: {}", library.path.display(), error);
+            }
+        }
         _storage.set_string(STORAGE_KEY, serde_json::to_string(&self).unwrap())
     }
 }
 
+fn load_error_window(state: &State, messages: &mut Vec<Msg>, ctx: &Context) {
+    let error = state.load_error.as_ref().unwrap();
+    Window::new("Saved data couldn't be loaded").collapsible(false).show(ctx, |ui| {
+        ui.label(RichText::new(&error.message).color(ui.visuals().warn_fg_color));
+        match &error.backup {
+            Ok(file) => ui.label(format!("the saved data was copied to {}", file.display())),
+            Err(backup_error) => ui.label(format!("backup failed: {}, nothing is saved until you choose", backup_error))
+        };
+        ui.separator();
+
+        match &error.recovered {
+            Some(recovered) => {
+                ui.label(format!("{} chords and {} songs can be recovered", recovered.chords.len(), recovered.songs.len()));
+                if !error.dropped.is_empty() {
+                    ui.label(format!("left out: {}", error.dropped.join(", ")));
+                }
+            }
+            None => {
+                ui.label("nothing could be recovered");
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(error.recovered.is_some(), Button::new("recover")).clicked() {
+                messages.push(Msg::RecoverState);
+            }
+            if ui.button("start fresh").clicked() {
+                messages.push(Msg::DismissLoadError);
+            }
+        });
+    });
+}
+
+// recorded steps oldest first, undone ones greyed out below them; clicking a step goes back or forward to it
+fn history_window(state: &State, messages: &mut Vec<Msg>, ctx: &Context) {
+    let history = &state.history;
+    Window::new("History").default_height(300.0).show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!history.done.is_empty(), Button::new("undo")).on_hover_text("Ctrl+Z").clicked() {
+                messages.push(Msg::Undo);
+            }
+            if ui.add_enabled(!history.undone.is_empty(), Button::new("redo")).on_hover_text("Ctrl+Shift+Z").clicked() {
+                messages.push(Msg::Redo);
+            }
+        });
+        ui.separator();
+
+        ScrollArea::vertical().show(ui, |ui| {
+            if history.done.is_empty() && history.undone.is_empty() {
+                ui.label("nothing changed yet");
+            }
+            for (idx, entry) in history.done.iter().enumerate() {
+                let is_current = idx + 1 == history.done.len();
+                if ui.add(SelectableLabel::new(is_current, &entry.label)).clicked() {
+                    messages.extend(std::iter::repeat_n(Msg::Undo, history.done.len() - idx - 1));
+                }
+            }
+            for (idx, entry) in history.undone.iter().rev().enumerate() {
+                let label = RichText::new(&entry.label).color(ui.visuals().weak_text_color());
+                if ui.add(SelectableLabel::new(false, label)).clicked() {
+                    messages.extend(std::iter::repeat_n(Msg::Redo, idx + 1));
+                }
+            }
+        });
+    });
+}
+
+// names the one chord about to go and the songs that picked it
+fn delete_chord_window(state: &State, messages: &mut Vec<Msg>, ctx: &Context) {
+    let chord = match state.chords.iter().find(|chord| Some(chord.id) == state.chord_to_delete) {
+        Some(chord) => chord,
+        None => return
+    };
+    let songs = state.songs.iter()
+        .filter(|song| song.preferences.values().any(|id| *id == chord.id))
+        .map(|song| song.name.as_str())
+        .sorted()
+        .collect::<Vec<_>>();
+
+    Window::new("Delete chord?").collapsible(false).resizable(false).show(ctx, |ui| {
+        ui.label(format!("{} on {}: {}", chord.name, chord.instrument.name, pitches_label(&chord.notes, &chord.instrument.tuning, 0)));
+        if !songs.is_empty() {
+            let warning = format!("picked in {}", songs.join(", "));
+            ui.label(RichText::new(warning).color(ui.visuals().warn_fg_color));
+        }
+        ui.label("it can be restored from the trash");
+        ui.horizontal(|ui| {
+            if ui.button("delete").clicked() {
+                messages.push(Msg::DeleteChord(chord.id));
+            }
+            if ui.button("cancel").clicked() {
+                messages.push(Msg::CancelDeleteChord);
+            }
+        });
+    });
+}
+
+fn delete_song_window(state: &State, messages: &mut Vec<Msg>, ctx: &Context) {
+    let song = match state.songs.iter().find(|song| Some(song.id) == state.song_to_delete) {
+        Some(song) => song,
+        None => return
+    };
+    Window::new("Delete song?").collapsible(false).resizable(false).show(ctx, |ui| {
+        ui.label(&song.name);
+        ui.label("archived songs are kept but hidden from the list");
+        ui.horizontal(|ui| {
+            if ui.button("delete").clicked() {
+                messages.push(Msg::DeleteSong(song.id));
+            }
+            if !song.archived && ui.button("archive instead").clicked() {
+                messages.push(Msg::SetSongArchived(song.id, true));
+                messages.push(Msg::CancelDeleteSong);
+            }
+            if ui.button("cancel").clicked() {
+                messages.push(Msg::CancelDeleteSong);
+            }
+        });
+    });
+}
+
+fn trash_window(state: &State, messages: &mut Vec<Msg>, ctx: &Context) {
+    Window::new("Trash").default_height(300.0).show(ctx, |ui| {
+        if state.trash.is_empty() {
+            ui.label("no deleted chords");
+        }
+        ScrollArea::vertical().show(ui, |ui| {
+            for chord in state.trash.iter() {
+                ui.horizontal(|ui| {
+                    ui.label(&chord.name).on_hover_text(pitches_label(&chord.notes, &chord.instrument.tuning, 0));
+                    ui.label(RichText::new(&chord.instrument.name).color(ui.visuals().weak_text_color()));
+                    if ui.small_button("restore").clicked() {
+                        messages.push(Msg::RestoreFromTrash(chord.id));
+                    }
+                    if ui.small_button("delete for good").clicked() {
+                        messages.push(Msg::PurgeChord(chord.id));
+                    }
+                });
+            }
+        });
+    });
+}
+
+fn library_window(state: &mut State, messages: &mut Vec<Msg>, ctx: &Context) {
+    Window::new("Library").show(ctx, |ui| {
+        match &state.library {
+            Some(library) => {
+                ui.label(format!("open: {}", library.path.display()));
+                ui.label(RichText::new("closing it brings back the chords and songs of the app storage").color(ui.visuals().weak_text_color()))
+            }
+            None => ui.label("chords and songs are kept in the app storage")
+        };
+        ui.separator();
+
+        ui.label("library folder:");
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut state.library_path_input);
+            let has_path = !state.library_path_input.is_empty();
+            if ui.add_enabled(has_path, Button::new("open")).clicked() {
+                messages.push(Msg::OpenLibrary(PathBuf::from(&state.library_path_input)));
+            }
+            let create = Button::new("create library here");
+            if ui.add_enabled(has_path, create).on_hover_text("writes chords.json and songs/ into the folder").clicked() {
+                messages.push(Msg::CreateLibrary(PathBuf::from(&state.library_path_input)));
+            }
+        });
+
+        if !state.recent_libraries.is_empty() {
+            ui.label("recent:");
+            for path in state.recent_libraries.iter() {
+                let is_open = state.library_path.as_ref() == Some(path);
+                if ui.add(SelectableLabel::new(is_open, path.display().to_string())).clicked() && !is_open {
+                    messages.push(Msg::OpenLibrary(path.clone()));
+                }
+            }
+        }
+
+        if state.library.as_ref().is_some_and(|library| library.conflict) {
+            ui.label(RichText::new("the files changed on disk and in the app, nothing is saved until one side is picked")
+                .color(ui.visuals().warn_fg_color));
+            ui.horizontal(|ui| {
+                if ui.button("reload from disk").clicked() {
+                    messages.push(Msg::ReloadLibrary);
+                }
+                if ui.button("keep the app's version").clicked() {
+                    messages.push(Msg::OverwriteLibrary);
+                }
+            });
+        }
+        if state.library.is_some() && ui.button("close library").clicked() {
+            messages.push(Msg::CloseLibrary);
+        }
+        if !state.library_status.is_empty() {
+            ui.label(&state.library_status);
+        }
+    });
+}
+
 fn chords_section(state: &mut State, messages: &mut Vec<Msg>, ctx: &Context) {
     SidePanel::new(Side::Left, "search").show(ctx, |ui| {
-        ui.text_edit_singleline(&mut state.chord_search_input);
+        ui.text_edit_singleline(&mut state.chord_search_input)
+            .on_hover_text("a name, or terms like root=D quality=maj7 bass=F# and contains notes F# A");
+        let query = ChordQuery::parse(&state.chord_search_input);
+        for error in query.errors.iter() {
+            ui.label(RichText::new(error).color(ui.visuals().warn_fg_color));
+        }
+        ui.horizontal(|ui| {
+            let tags = state.chord_tags().into_iter().cloned().collect::<Vec<_>>();
+            ComboBox::from_id_source("chord_tag_filter")
+                .selected_text(state.chord_tag_filter.as_deref().unwrap_or("all tags"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut state.chord_tag_filter, None, "all tags");
+                    for tag in tags {
+                        ui.selectable_value(&mut state.chord_tag_filter, Some(tag.clone()), tag);
+                    }
+                });
+            ui.toggle_value(&mut state.favorites_only, "★").on_hover_text("favorites only");
+        });
+        ComboBox::from_id_source("chord_sort")
+            .selected_text(format!("sort by {}", state.chord_sort.name()))
+            .show_ui(ui, |ui| {
+                for sort in ChordSort::all() {
+                    ui.selectable_value(&mut state.chord_sort, sort, sort.name());
+                }
+            });
+        CollapsingHeader::new("by shape").show(ui, |ui| shape_search_section(state, messages, ctx, ui));
         ui.separator();
 
-        let chords_prepared = state.chords
+        let mut chords_prepared = state.chords
             .iter()
+            .filter(|chord| state.chord_passes_filter(chord))
             .map(|chord| &chord.name)
+            .unique()
             .cloned()
-            .collect::<HashSet<String>>()
-            .into_iter()
-            .filter(|chord_name| chord_name.contains(&state.chord_search_input))
-            .sorted();
+            .collect::<Vec<_>>();
+        state.sort_chord_names(&mut chords_prepared);
+        if !query.is_empty() {
+            let scored = chords_prepared.into_iter()
+                .filter_map(|name| {
+                    let voicings = state.chords.iter().filter(|chord| chord.name == name).collect::<Vec<_>>();
+                    query.score(&name, &voicings).map(|score| (name, score))
+                })
+                .collect::<Vec<_>>();
+            // the sort is stable, equal scores keep the chosen order
+            chords_prepared = match query.is_structural() {
+                true => scored.into_iter().map(|(name, _)| name).collect(),
+                false => scored.into_iter().sorted_by_key(|(_, score)| -score).map(|(name, _)| name).collect()
+            };
+        }
 
-        for chord_name in chords_prepared {
-            let label = SelectableLabel::new(state.selected_chord == chord_name, &chord_name);
-            if ui.add(label).clicked() {
-                messages.push(Msg::SelectChord(chord_name.clone()));
+        ScrollArea::vertical().id_source("chord_names").show(ui, |ui| {
+            for chord_name in chords_prepared {
+                let is_favorite = state.chords.iter().any(|chord| chord.name == chord_name && chord.favorite);
+                let text = if is_favorite { format!("★ {}", chord_name) } else { chord_name.clone() };
+                let label = SelectableLabel::new(state.selected_chord == chord_name, text);
+                if ui.add(label).clicked() {
+                    messages.push(Msg::SelectChord(chord_name.clone()));
+                }
             }
-        }
+        });
     });
 
     CentralPanel::default().show(ctx, |ui| {
-        if state.selected_chord == "" {
+        if state.selected_chord.is_empty() {
             ui.label("Select a chord to continue please");
         } else {
             ui.horizontal(|ui| {
                 ui.label(RichText::new(&state.selected_chord).font(FontId::proportional(24.0)));
                 if ui.button("+").clicked() {
-                    messages.push(Msg::AddEmptyChord(state.selected_chord.clone()));
+                    messages.push(Msg::AddEmptyChord(state.selected_chord.clone(), Instrument::default()));
                 }
+                ui.toggle_value(&mut state.voicing_generator.is_open, "voicings");
+                ui.toggle_value(&mut state.neck_view.is_open, "neck");
+                ui.toggle_value(&mut state.midi_input.is_open, "midi in").on_hover_text("play a chord in or load a .mid file");
+                ui.separator();
+                ComboBox::from_id_source("play_style")
+                    .selected_text(state.play_style.name())
+                    .show_ui(ui, |ui| {
+                        for style in PlayStyle::all() {
+                            ui.selectable_value(&mut state.play_style, style, style.name());
+                        }
+                    });
+                ui.label("wav file or folder:");
+                ui.text_edit_singleline(&mut state.wav_path);
             });
+            if !state.sound_status.is_empty() {
+                ui.label(&state.sound_status);
+            }
+            if state.voicing_generator.is_open {
+                voicings_section(state, messages, ctx, ui);
+                ui.separator();
+            }
+            if state.neck_view.is_open {
+                neck_section(state, ui);
+                ui.separator();
+            }
+            if state.midi_input.is_open {
+                midi_input_section(state, messages, ctx, ui);
+                ui.separator();
+            }
+            // the tag buffer is taken out while the chords are borrowed, sound is made after
+            let mut new_tag = std::mem::take(&mut state.new_tag_input);
+            let mut play = None;
+            let mut save = None;
             ui.horizontal(|ui| {
-                for chord in state.chords.iter_mut().filter(|chord| chord.name == state.selected_chord) {
-                    let draw_response = draw_chord(ctx, ui, &mut chord.notes);
-                    if draw_response.is_deleted {
-                        messages.push(Msg::DeleteChord(chord.id));
+                let known_tags = state.chord_tags();
+                let shown = state.chords.iter().filter(|chord| chord.name == state.selected_chord && state.chord_passes_filter(chord));
+                for chord in shown {
+                    ui.vertical(|ui| {
+                        let draw_response = draw_chord(ctx, ui, &chord.notes, &chord.instrument, 0);
+                        if let Some(notes) = draw_response.edited {
+                            messages.push(Msg::SetChordNotes(chord.id, notes));
+                        }
+                        if draw_response.is_deleted {
+                            messages.push(Msg::ConfirmDeleteChord(chord.id));
+                        }
+                        draw_response.response.on_hover_text(pitches_label(&chord.notes, &chord.instrument.tuning, 0));
+                        let mut instrument = chord.instrument.clone();
+                        if instrument_editor(ui, ("chord_instrument", chord.id), &mut instrument) {
+                            messages.push(Msg::SetChordInstrument(chord.id, instrument));
+                        }
+                        chord_organizer(ui, messages, chord, &known_tags, &mut new_tag);
+                        chord_analysis(ui, chord);
+                        ui.horizontal(|ui| {
+                            if ui.small_button("▶").on_hover_text(state.play_style.name()).clicked() {
+                                play = Some(chord.clone());
+                            }
+                            if ui.small_button("save wav").clicked() {
+                                save = Some(chord.clone());
+                            }
+                            if ui.small_button("copy svg").on_hover_text("copy the diagram as an svg document").clicked() {
+                                let svg = chord_svg(chord, 0, &SvgStyle::default());
+                                ui.output_mut(|o| o.copied_text = svg);
+                            }
+                        });
+                    });
+                }
+            });
+            state.new_tag_input = new_tag;
+            if let Some(chord) = play {
+                let style = state.play_style;
+                let played = state.player.play(|rate| render_chord(&chord.notes, &chord.instrument.tuning, 0, style, rate));
+                state.sound_status = played.err().unwrap_or_default();
+            }
+            if let Some(chord) = save {
+                state.sound_status = match synth::export_path(&chord, state.play_style, &PathBuf::from(&state.wav_path)) {
+                    Ok(file) => format!("saved {}", file.display()),
+                    Err(error) => format!("saving failed: {}", error)
+                };
+            }
+        }
+    });
+}
+
+fn voicings_section(state: &mut State, messages: &mut Vec<Msg>, ctx: &Context, ui: &mut Ui) {
+    let generator = &mut state.voicing_generator;
+
+    ui.horizontal(|ui| {
+        if instrument_editor(ui, "voicing_instrument", &mut generator.instrument) {
+            generator.filter.strings = (1..=generator.instrument.string_count()).collect();
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.add(DragValue::new(&mut generator.filter.max_stretch).clamp_range(1..=7).prefix("stretch: "));
+        ui.add(DragValue::new(&mut generator.filter.max_muted).clamp_range(0..=generator.instrument.string_count()).prefix("muted: "));
+        ui.label("strings:");
+        for string in 1..=generator.instrument.string_count() {
+            let mut enabled = generator.filter.strings.contains(&string);
+            if ui.checkbox(&mut enabled, string.to_string()).changed() {
+                if enabled {
+                    generator.filter.strings.push(string);
+                } else {
+                    generator.filter.strings.retain(|s| *s != string);
+                }
+            }
+        }
+        if ui.button("find").clicked() {
+            generator.run(&state.selected_chord);
+        }
+    });
+
+    if let Some(error) = &generator.error {
+        ui.label(RichText::new(error).color(ui.visuals().warn_fg_color));
+    }
+
+    ScrollArea::horizontal().id_source("voicings").show(ui, |ui| {
+        ui.horizontal(|ui| {
+            for (idx, notes) in generator.results.iter_mut().enumerate() {
+                ui.vertical(|ui| {
+                    // results are scratch space outside the history, edits apply right away
+                    if let Some(edited) = draw_chord(ctx, ui, notes, &generator.instrument, 0).edited {
+                        *notes = edited;
+                    }
+                    if ui.button("add").on_hover_text(format!("voicing {}", idx + 1)).clicked() {
+                        messages.push(Msg::AddChordShape(state.selected_chord.clone(), generator.instrument.clone(), notes.clone()));
+                    }
+                });
+            }
+        });
+    });
+}
+
+// the whole neck with a scale, or the tones or CAGED shapes of the selected chord
+fn neck_section(state: &mut State, ui: &mut Ui) {
+    let view = &mut state.neck_view;
+    ui.horizontal(|ui| {
+        instrument_editor(ui, "neck_instrument", &mut view.instrument);
+        ComboBox::from_id_source("neck_overlay")
+            .selected_text(view.overlay.name())
+            .show_ui(ui, |ui| {
+                for overlay in Overlay::all() {
+                    ui.selectable_value(&mut view.overlay, overlay, overlay.name());
+                }
+            });
+        match view.overlay {
+            Overlay::Scale => {
+                let spelled = |root: PitchClass, scale: Scale| root.spell(scale.key(root).prefers_flats()).to_string();
+                ComboBox::from_id_source("neck_scale_root")
+                    .width(50.0)
+                    .selected_text(spelled(view.scale_root, view.scale))
+                    .show_ui(ui, |ui| {
+                        for root in PitchClass::all() {
+                            ui.selectable_value(&mut view.scale_root, root, spelled(root, view.scale));
+                        }
+                    });
+                ComboBox::from_id_source("neck_scale")
+                    .selected_text(view.scale.name())
+                    .show_ui(ui, |ui| {
+                        for scale in Scale::all() {
+                            ui.selectable_value(&mut view.scale, scale, scale.name());
+                        }
+                    });
+            }
+            Overlay::Caged => {
+                ComboBox::from_id_source("neck_caged_shape")
+                    .width(50.0)
+                    .selected_text(view.caged_shape.map(|shape| shape.name()).unwrap_or("all"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut view.caged_shape, None, "all");
+                        for shape in CagedShape::all() {
+                            ui.selectable_value(&mut view.caged_shape, Some(shape), shape.name());
+                        }
+                    });
+            }
+            Overlay::ChordTones => ()
+        }
+        ui.separator();
+        ui.radio_value(&mut view.labels, Labels::Notes, "notes");
+        ui.radio_value(&mut view.labels, Labels::Degrees, "degrees");
+    });
+
+    let chord = ChordSymbol::parse(&state.selected_chord).ok();
+    match view.marks(chord.as_ref()) {
+        Ok(marks) => {
+            ScrollArea::horizontal().id_source("neck").show(ui, |ui| draw_neck(ui, &view.instrument, &marks));
+        }
+        Err(error) => {
+            ui.label(RichText::new(error).color(ui.visuals().warn_fg_color));
+        }
+    }
+}
+
+// the last chord played or loaded, put on the strings and ready to be added
+fn midi_input_section(state: &mut State, messages: &mut Vec<Msg>, ctx: &Context, ui: &mut Ui) {
+    let input = &mut state.midi_input;
+    input.poll();
+
+    ui.horizontal(|ui| {
+        if instrument_editor(ui, "midi_instrument", &mut input.instrument) && !input.notes.is_empty() {
+            input.propose(input.notes.clone());
+        }
+        let listening = input.is_listening();
+        ui.add_enabled_ui(!listening, |ui| {
+            ComboBox::from_id_source("midi_port")
+                .selected_text(input.port.clone().unwrap_or("virtual port".to_owned()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut input.port, None, "virtual port")
+                        .on_hover_text("other programs and loopback devices can send to it");
+                    match MidiInput::ports() {
+                        Ok(ports) => for port in ports {
+                            ui.selectable_value(&mut input.port, Some(port.clone()), port);
+                        },
+                        Err(error) => {
+                            ui.label(error);
+                        }
+                    }
+                });
+        });
+        if listening {
+            if ui.button("stop").clicked() {
+                input.stop();
+            }
+        } else if ui.button("listen").clicked() {
+            let ctx = ctx.clone();
+            if let Err(error) = input.listen(move || ctx.request_repaint()) {
+                input.status = error;
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label(".mid file:");
+        ui.text_edit_singleline(&mut input.path);
+        if ui.button("load").clicked() {
+            input.load_file();
+        }
+    });
+
+    if !input.status.is_empty() {
+        ui.label(RichText::new(&input.status).color(ui.visuals().warn_fg_color));
+    }
+    if input.notes.is_empty() {
+        ui.label("play a chord or load a file");
+        return;
+    }
+    ui.label(input.notes.iter().map(|note| Pitch::from_midi(*note, false).to_string()).join(" "));
+    if input.shape.is_empty() {
+        return;
+    }
+    ui.horizontal(|ui| {
+        // the proposal is scratch space like the generated voicings, edits apply right away
+        if let Some(edited) = draw_chord(ctx, ui, &input.shape, &input.instrument, 0).edited {
+            input.shape = edited;
+        }
+        ui.vertical(|ui| {
+            if ui.button(format!("add to {}", state.selected_chord)).clicked() {
+                messages.push(Msg::AddChordShape(state.selected_chord.clone(), input.instrument.clone(), input.shape.clone()));
+            }
+            ui.horizontal_wrapped(|ui| {
+                ui.label("reads as");
+                for candidate in analyze(&input.shape, &input.instrument.tuning).iter().take(3) {
+                    if ui.link(candidate.name()).on_hover_text("add it under this name").clicked() {
+                        messages.push(Msg::AddChordShape(candidate.name(), input.instrument.clone(), input.shape.clone()));
+                        messages.push(Msg::SelectChord(candidate.name()));
                     }
                 }
             });
+        });
+    });
+}
+
+// favorite, difficulty and tags of one voicing, edited on a copy and sent as one message
+fn chord_organizer(ui: &mut Ui, messages: &mut Vec<Msg>, chord: &Chord, known_tags: &[&String], new_tag: &mut String) {
+    let mut edited = chord.clone();
+    ui.horizontal(|ui| {
+        ui.toggle_value(&mut edited.favorite, "★").on_hover_text("favorite");
+        ComboBox::from_id_source(("chord_difficulty", chord.id))
+            .width(40.0)
+            .selected_text(edited.difficulty.map(|d| d.to_string()).unwrap_or("-".to_owned()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut edited.difficulty, None, "-");
+                for difficulty in 1..=MAX_DIFFICULTY {
+                    ui.selectable_value(&mut edited.difficulty, Some(difficulty), difficulty.to_string());
+                }
+            })
+            .response
+            .on_hover_text("difficulty, 1 is easy");
+    });
+    tag_editor(ui, &mut edited.tags, known_tags, new_tag);
+    if edited != *chord {
+        messages.push(Msg::UpdateChord(edited));
+    }
+}
+
+// tags as buttons that remove them, and a menu adding a known or a new one
+fn tag_editor(ui: &mut Ui, tags: &mut Vec<String>, known_tags: &[&String], new_tag: &mut String) {
+    ui.horizontal_wrapped(|ui| {
+        for tag in tags.clone() {
+            if ui.small_button(&tag).on_hover_text("remove tag").clicked() {
+                tags.retain(|t| *t != tag);
+            }
+        }
+        ui.menu_button("+", |ui| {
+            let addable = known_tags.iter().filter(|tag| !tags.contains(tag)).map(|tag| (*tag).clone()).collect::<Vec<_>>();
+            for tag in addable {
+                if ui.button(&tag).clicked() {
+                    tags.push(tag);
+                    ui.close_menu();
+                }
+            }
+            let response = ui.text_edit_singleline(new_tag);
+            let tag = new_tag.trim().to_lowercase();
+            if response.lost_focus() && !tag.is_empty() && !tags.contains(&tag) {
+                tags.push(tag);
+                new_tag.clear();
+                ui.close_menu();
+            }
+        });
+    });
+}
+
+// candidate names for the shape, with a warning if the stored name doesn't fit it
+fn chord_analysis(ui: &mut Ui, chord: &Chord) {
+    let candidates = analyze(&chord.notes, &chord.instrument.tuning);
+    if candidates.is_empty() {
+        return;
+    }
+
+    ui.horizontal_wrapped(|ui| {
+        for candidate in candidates.iter().take(3) {
+            ui.label(candidate.name()).on_hover_text(candidate.description());
         }
     });
+    if !name_matches(&chord.name, &candidates) {
+        let warning = format!("shape reads as {}, not {}", candidates[0].name(), chord.name);
+        ui.label(RichText::new(warning).color(ui.visuals().warn_fg_color));
+    }
 }
 
 fn songs_section(state: &mut State, messages: &mut Vec<Msg>, ctx: &Context) {
     SidePanel::new(Side::Left, "search").show(ctx, |ui| {
         ui.horizontal(|ui| {
-            ui.text_edit_singleline(&mut state.song_search_input);
-            if ui.button("+").clicked() && state.song_search_input.len() > 0 {
+            ui.text_edit_singleline(&mut state.song_search_input)
+                .on_hover_text("words from the lyrics or title, and terms like chord:Bm key:G artist:name tag:name");
+            if ui.button("+").clicked() && !state.song_search_input.is_empty() {
                 messages.push(Msg::AddEmptySong(state.song_search_input.clone()));
             }
         });
+        for error in SongQuery::parse(&state.song_search_input).errors {
+            ui.label(RichText::new(error).color(ui.visuals().warn_fg_color));
+        }
+        ui.checkbox(&mut state.show_archived_songs, "show archived");
         ui.separator();
 
-        let songs_prepared = state.songs
-            .iter()
-            .map(|song| &song.name)
-            .cloned()
-            .collect::<HashSet<String>>()
-            .into_iter()
-            .filter(|song_name| song_name.contains(&state.song_search_input))
-            .sorted();
-
-        for song_name in songs_prepared {
-            let label = SelectableLabel::new(state.selected_song == song_name, &song_name);
-            if ui.add(label).clicked() {
-                messages.push(Msg::SelectSong(song_name.clone()));
+        let found = state.search_songs();
+        let listed_ids = found.iter().map(|found_song| found_song.id).collect::<Vec<_>>();
+        ScrollArea::vertical().id_source("song_results").max_height(ui.available_height() * 0.6).show(ui, |ui| {
+            for found_song in found {
+                song_result(state, messages, ui, found_song);
             }
-        }
+        });
+
+        ui.separator();
+        CollapsingHeader::new("ChordPro").show(ui, |ui| chordpro_section(state, messages, ui));
+        CollapsingHeader::new("PDF").show(ui, |ui| pdf_section(state, &listed_ids, ui));
+        CollapsingHeader::new("MIDI").show(ui, |ui| midi_section(state, ui));
     });
 
     CentralPanel::default().show(ctx, |ui| {
-        if state.selected_song == "" {
-            ui.label("Select a song to continue please");
-        } else {
-            let song = state.songs.iter_mut().find(|s| s.name == state.selected_song).unwrap();
+        // the selected song can be gone after an undo
+        if let Some(song) = state.songs.iter().find(|s| Some(s.id) == state.selected_song) {
+            // fields are edited on a copy and sent as one message, so they can be undone
+            let mut edited = song.clone();
 
-            if ui.text_edit_singleline(&mut song.name).changed() {
-                messages.push(Msg::SelectSong(song.name.clone()));
+            ui.horizontal(|ui| {
+                // the name is typed into a buffer and taken when the field is left, if no other song has it
+                let name_id = Id::new(SONG_NAME_FIELD);
+                let is_renaming = state.song_rename.as_ref().is_some_and(|(id, _)| *id == song.id);
+                if !is_renaming || !ui.memory(|m| m.has_focus(name_id)) {
+                    state.song_rename = Some((song.id, song.name.clone()));
+                }
+                let (_, name) = state.song_rename.as_mut().unwrap();
+                let response = ui.add(TextEdit::singleline(name).id(name_id));
+                let is_taken = song_name_taken(&state.songs, song.id, name);
+                if response.lost_focus() && !is_taken && name.trim() != song.name {
+                    messages.push(Msg::RenameSong(song.id, name.clone()));
+                }
+                instrument_editor(ui, "song_instrument", &mut edited.instrument);
+                if is_taken {
+                    ui.label(RichText::new("another song has this name").color(ui.visuals().warn_fg_color));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("artist:");
+                ui.text_edit_singleline(&mut edited.artist);
+            });
+            ui.horizontal(|ui| {
+                ui.label("tags:");
+                let known_tags = state.songs.iter().flat_map(|song| song.tags.iter()).unique().sorted().collect::<Vec<_>>();
+                tag_editor(ui, &mut edited.tags, &known_tags, &mut state.new_tag_input);
+            });
+            ui.horizontal(|ui| {
+                ui.label("transpose:");
+                if ui.button("-1").clicked() {
+                    messages.push(Msg::TransposeSong(song.id, -1));
+                }
+                if ui.button("+1").clicked() {
+                    messages.push(Msg::TransposeSong(song.id, 1));
+                }
+                if let Some(key) = song.key() {
+                    ui.label(format!("key: {}", key));
+                }
+                ui.separator();
+                ui.add(DragValue::new(&mut edited.capo).clamp_range(0..=12).prefix("capo: "));
+            });
+            if edited != *song {
+                messages.push(Msg::UpdateSong(edited));
+            }
+            for (shape_name, id) in song.missing_preferences(&state.chords) {
+                ui.horizontal(|ui| {
+                    let warning = format!("the picked voicing of {} was deleted", shape_name);
+                    ui.label(RichText::new(warning).color(ui.visuals().warn_fg_color));
+                    if state.trash.iter().any(|chord| chord.id == id) && ui.small_button("restore").clicked() {
+                        messages.push(Msg::RestoreFromTrash(id));
+                    }
+                });
+            }
+            if song.capo > 0 {
+                ui.horizontal_wrapped(|ui| {
+                    ui.label("play:");
+                    for (sounding, shape) in song.capo_chart() {
+                        ui.label(format!("{} as {}", sounding, shape));
+                    }
+                });
             }
             ui.separator();
-            let text_edit_output = TextEdit::multiline(&mut song.text)
+            let text_id = Id::new(SONG_TEXT_FIELD);
+            if let Some(line) = state.song_jump.take() {
+                let position = song.text.split('\n').take(line).map(|l| l.chars().count() + 1).sum();
+                let mut edit_state = text_edit::TextEditState::load(ctx, text_id).unwrap_or_default();
+                edit_state.set_ccursor_range(Some(text::CCursorRange::one(text::CCursor::new(position))));
+                edit_state.store(ctx, text_id);
+                ctx.memory_mut(|m| m.request_focus(text_id));
+            }
+            let mut text = song.text.clone();
+            let text_edit_output = TextEdit::multiline(&mut text)
+                .id(text_id)
                 .min_size(ui.available_size())
                 .show(ui);
+            if text != song.text {
+                messages.push(Msg::SetSongText(song.id, text));
+            }
 
-            if let Some(Some(cursor)) = text_edit_output.cursor_range.map(|cr| cr.single()) {
-                let cursor_position = cursor.ccursor.index;
-                let possible_chord_str = extract_word_from_cursor_position(&song.text, cursor_position);
-
-                // TODO: how to solve cloning???
-                let chords_binding = state.chords.to_vec();
-                let found_chords_to_read: Vec<&Chord> = chords_binding.iter().filter(|chord| chord.name == possible_chord_str).sorted_by_key(|c| c.id).collect();
+            let sheet = song.sheet();
+            let cursor_chord = text_edit_output.cursor_range
+                .and_then(|cr| cr.single())
+                .and_then(|cursor| sheet.chord_at(cursor.pcursor.paragraph, cursor.pcursor.offset).map(|event| (cursor, event)));
+            if let Some((cursor, event)) = cursor_chord {
+                let possible_chord_string = event.symbol.to_string();
+                let possible_chord_str = possible_chord_string.as_str();
+                // the library is looked up by the shape that is actually played
+                let shape_name = song.shape_name(possible_chord_str);
+                let window_title = if shape_name == possible_chord_str {
+                    shape_name.clone()
+                } else {
+                    format!("{} (play {})", possible_chord_str, shape_name)
+                };
 
-                // display chord list
-                let preference = song.preferences.get(possible_chord_str);
-                let mut found_chords: Vec<&mut Chord> = state.chords.iter_mut().filter(|chord| chord.name == possible_chord_str).sorted_by_key(|c| c.id).collect();
-                let target_chord: Option<&mut &mut Chord> = found_chords.iter_mut().find(|chord| preference.map_or(true, |p| *p == chord.id));
+                let found_chords: Vec<&Chord> = state.chords.iter().filter(|chord| chord.name == shape_name).sorted_by_key(|c| c.id).collect();
                 let chord_drawing_position = text_edit_output.text_clip_rect.min + Vec2::new(350.0, cursor.rcursor.row as f32 * 10.0);
-                match target_chord {
+                match song.preferred_chord(&state.chords, &shape_name) {
                     Some(chord) => {
-                        Window::new(&chord.name)
+                        Window::new(&window_title)
                             .current_pos(chord_drawing_position)
                             .show(ctx, |ui| {
                                 ui.horizontal(|ui| {
-                                    draw_chord(ctx, ui, &mut chord.notes);
-
-                                    if found_chords_to_read.len() > 1 && ui.button(">").clicked() {
-                                        match found_chords_to_read.iter().find(|c| c.id > chord.id) {
-                                            Some(next_chord) => {
-                                                messages.push(Msg::InsertSongPreference(song.name.clone(), (*next_chord).clone()));
-                                            },
-                                            None => {
-                                                let first_chord = found_chords_to_read.first().unwrap();
-                                                messages.push(Msg::InsertSongPreference(song.name.clone(), (*first_chord).clone()));
-                                            }
-                                        }
+                                    let draw_response = draw_chord(ctx, ui, &chord.notes, &chord.instrument, song.capo);
+                                    if let Some(notes) = draw_response.edited {
+                                        messages.push(Msg::SetChordNotes(chord.id, notes));
+                                    }
+                                    if draw_response.is_deleted {
+                                        messages.push(Msg::ConfirmDeleteChord(chord.id));
+                                    }
+                                    draw_response.response.on_hover_text(pitches_label(&chord.notes, &chord.instrument.tuning, song.capo));
+                                    if chord.instrument != song.instrument {
+                                        ui.label(format!("for {} in {}", chord.instrument.name, chord.instrument.tuning.name));
+                                    }
 
+                                    if found_chords.len() > 1 && ui.button(">").clicked() {
+                                        let next_chord = found_chords.iter().find(|c| c.id > chord.id).unwrap_or(&found_chords[0]);
+                                        messages.push(Msg::InsertSongPreference(song.id, (*next_chord).clone()));
                                     }
                                 });
                             });
                     },
-                    None if is_like_chord(possible_chord_str) => {
-                        Window::new(possible_chord_str)
+                    None => {
+                        Window::new(&window_title)
                             .fixed_pos(chord_drawing_position)
                             .show(ctx, |ui| {
                                 if(ui.button("Create")).clicked() {
-                                    messages.push(Msg::AddEmptyChord(possible_chord_str.to_string()));
+                                    messages.push(Msg::AddEmptyChord(shape_name.clone(), song.instrument.clone()));
                                 }
                             });
-                    },
-                    None => ()
+                    }
                 }
             }
+        } else {
+            ui.label("Select a song to continue please");
         }
     });
 }
 
-fn extract_word_from_cursor_position(text: &String, cursor_position: usize) -> &str {
-    if text.len() == 0 {
-        ""
-    } else {
-        let chars: Vec<char> = text.chars().collect();
-        let stop_chars = [' ', '\n', '\t'];
-        // debug!("{}", cursor_position);
+// the song with its menu, and the lines that matched the search
+fn song_result(state: &State, messages: &mut Vec<Msg>, ui: &mut Ui, found: SongMatch) {
+    let song = match state.songs.iter().find(|song| song.id == found.id) {
+        Some(song) => song,
+        None => return
+    };
+    let mut text = RichText::new(&song.name);
+    if song.archived {
+        text = text.color(ui.visuals().weak_text_color());
+    }
+    let label = ui.add(SelectableLabel::new(state.selected_song == Some(song.id), text));
+    if label.clicked() {
+        messages.push(Msg::SelectSong(song.id));
+    }
+    label.context_menu(|ui| {
+        if ui.button("rename").clicked() {
+            messages.push(Msg::SelectSong(song.id));
+            ui.memory_mut(|m| m.request_focus(Id::new(SONG_NAME_FIELD)));
+            ui.close_menu();
+        }
+        if ui.button("duplicate").clicked() {
+            messages.push(Msg::DuplicateSong(song.id));
+            ui.close_menu();
+        }
+        let archive = if song.archived { "unarchive" } else { "archive" };
+        if ui.button(archive).clicked() {
+            messages.push(Msg::SetSongArchived(song.id, !song.archived));
+            ui.close_menu();
+        }
+        if ui.button("delete").clicked() {
+            messages.push(Msg::ConfirmDeleteSong(song.id));
+            ui.close_menu();
+        }
+    });
 
-        let mut start_idx = if cursor_position >= chars.len() {
-            chars.len() - 1
-        } else {
-            cursor_position
-        };
-        // debug!("start_idx: {}, len: {}", start_idx, chars.len());
-        while !(start_idx < 1 || stop_chars.contains(&chars[start_idx - 1])) {
-            start_idx = start_idx - 1;
+    for snippet in found.snippets {
+        let mut job = text::LayoutJob::default();
+        let plain = TextFormat { font_id: FontId::proportional(11.0), color: ui.visuals().weak_text_color(), ..Default::default() };
+        let highlighted = TextFormat { color: ui.visuals().strong_text_color(), background: ui.visuals().selection.bg_fill, ..plain.clone() };
+        let mut at = 0;
+        for range in snippet.highlights {
+            job.append(&snippet.text[at..range.start], 0.0, plain.clone());
+            job.append(&snippet.text[range.clone()], 0.0, highlighted.clone());
+            at = range.end;
+        }
+        job.append(&snippet.text[at..], 0.0, plain);
+        let response = ui.indent(("snippet", song.id, snippet.line), |ui| ui.add(Label::new(job).sense(Sense::click()))).inner;
+        if response.on_hover_text(format!("line {}", snippet.line + 1)).clicked() {
+            messages.push(Msg::JumpToSongLine(song.id, snippet.line));
         }
+    }
+}
 
-        let mut end_idx = if cursor_position >= chars.len() {
-            chars.len() - 1
-        } else {
-            cursor_position
-        };
-        // debug!("end_idx: {}, len: {}", end_idx, chars.len());
-        while !(end_idx == chars.len() || stop_chars.contains(&chars[end_idx])) {
-            end_idx = end_idx + 1;
+fn chordpro_section(state: &mut State, messages: &mut Vec<Msg>, ui: &mut Ui) {
+    ui.label("file or folder:");
+    ui.text_edit_singleline(&mut state.chordpro_path);
+    let path = PathBuf::from(&state.chordpro_path);
+
+    ui.horizontal(|ui| {
+        if ui.button("import").clicked() {
+            state.chordpro_status = match chordpro::import_path(&path) {
+                Ok(songs) => {
+                    let status = format!("imported {} songs", songs.len());
+                    messages.extend(songs.into_iter().map(Msg::AddSong));
+                    status
+                }
+                Err(error) => format!("import failed: {}", error)
+            };
         }
 
-        // debug!("calc: {}, start_idx: {}, end_idx: {}", text.char_range(Range { start: start_idx, end: end_idx }), start_idx, end_idx);
-        text.char_range(Range { start: start_idx, end: end_idx })
+        let selected_song = state.songs.iter().find(|s| Some(s.id) == state.selected_song);
+        if ui.add_enabled(selected_song.is_some(), Button::new("export")).clicked() {
+            state.chordpro_status = match chordpro::export_path(selected_song.unwrap(), &path) {
+                Ok(file) => format!("saved {}", file.display()),
+                Err(error) => format!("export failed: {}", error)
+            };
+        }
+    });
+
+    if !state.chordpro_status.is_empty() {
+        ui.label(&state.chordpro_status);
+    }
+}
+
+// the selected song, or every song the search currently lists
+fn pdf_section(state: &mut State, listed_ids: &[models::Id], ui: &mut Ui) {
+    ui.label("file or folder:");
+    ui.text_edit_singleline(&mut state.pdf_path);
+    let path = PathBuf::from(&state.pdf_path);
+
+    let selected_song = state.songs.iter().filter(|s| Some(s.id) == state.selected_song).collect::<Vec<_>>();
+    let listed_songs = listed_ids.iter().filter_map(|id| state.songs.iter().find(|s| s.id == *id)).collect::<Vec<_>>();
+    let mut export = None;
+    ui.horizontal(|ui| {
+        if ui.add_enabled(!selected_song.is_empty(), Button::new("export song")).clicked() {
+            export = Some(&selected_song);
+        }
+        if ui.add_enabled(!listed_songs.is_empty(), Button::new("export listed")).clicked() {
+            export = Some(&listed_songs);
+        }
+    });
+
+    if let Some(songs) = export {
+        state.pdf_status = match songbook::export_path(songs, &state.chords, &path) {
+            Ok(file) => format!("saved {}", file.display()),
+            Err(error) => format!("export failed: {}", error)
+        };
+    }
+    if !state.pdf_status.is_empty() {
+        ui.label(&state.pdf_status);
+    }
+}
+
+// the chord progression of the selected song, strummed with the shapes it picked
+fn midi_section(state: &mut State, ui: &mut Ui) {
+    let settings = &mut state.midi_settings;
+    ui.horizontal(|ui| {
+        ui.add(DragValue::new(&mut settings.tempo).clamp_range(20..=300).suffix(" bpm"));
+        ui.add(DragValue::new(&mut settings.beats_per_bar).clamp_range(1..=16));
+        ui.label("/");
+        ComboBox::from_id_source("midi_beat_unit").width(40.0).selected_text(settings.beat_unit.to_string()).show_ui(ui, |ui| {
+            for unit in [2, 4, 8] {
+                ui.selectable_value(&mut settings.beat_unit, unit, unit.to_string());
+            }
+        });
+    });
+    ui.add(DragValue::new(&mut settings.beats_per_chord).clamp_range(1..=16).prefix("beats per chord: "));
+    ui.horizontal(|ui| {
+        ui.label("strum:");
+        ui.text_edit_singleline(&mut settings.pattern)
+            .on_hover_text("one letter per eighth note: D strums down, U strums up, - lets it ring; empty holds the chord");
+    });
+    ui.label("file or folder:");
+    ui.text_edit_singleline(&mut settings.path);
+
+    let selected_song = state.songs.iter().find(|s| Some(s.id) == state.selected_song);
+    if ui.add_enabled(selected_song.is_some(), Button::new("export song")).clicked() {
+        let settings = &state.midi_settings;
+        state.midi_settings.status = match midi::export_path(selected_song.unwrap(), &state.chords, settings, &PathBuf::from(&settings.path)) {
+            Ok(file) => format!("saved {}", file.display()),
+            Err(error) => format!("export failed: {}", error)
+        };
+    }
+    if !state.midi_settings.status.is_empty() {
+        ui.label(&state.midi_settings.status);
     }
 }
 
-fn is_like_chord(possible_chord: &str) -> bool {
-    let notes_chars = Note::all().map(|n| n.to_string().chars().next().unwrap());
-    possible_chord.starts_with(|c| {
-        notes_chars.contains(&c)
-    })
+// click dots on the blank diagram, stored chords having them are listed with what the shape reads as
+fn shape_search_section(state: &mut State, messages: &mut Vec<Msg>, ctx: &Context, ui: &mut Ui) {
+    let search = &mut state.shape_search;
+    instrument_editor(ui, "shape_search_instrument", &mut search.instrument);
+    ui.horizontal(|ui| {
+        if let Some(notes) = draw_chord(ctx, ui, &search.notes, &search.instrument, 0).edited {
+            search.notes = notes;
+        }
+        if ui.add_enabled(!search.notes.is_empty(), Button::new("clear")).clicked() {
+            search.notes.clear();
+        }
+    });
+    if search.notes.is_empty() {
+        ui.label("click the diagram to place dots");
+        return;
+    }
+
+    let readings = analyze(&search.notes, &search.instrument.tuning);
+    ui.horizontal_wrapped(|ui| {
+        ui.label("reads as");
+        for candidate in readings.iter().take(3) {
+            if ui.link(candidate.name()).clicked() {
+                messages.push(Msg::SelectChord(candidate.name()));
+            }
+        }
+    });
+    let matches = search.matches(&state.chords);
+    if matches.is_empty() {
+        ui.label("no stored chord has these dots");
+    }
+    for (chord, is_exact) in matches {
+        let text = if is_exact { chord.name.clone() } else { format!("{} (contains)", chord.name) };
+        let selected = state.selected_chord == chord.name;
+        if ui.add(SelectableLabel::new(selected, text)).on_hover_text(pitches_label(&chord.notes, &chord.instrument.tuning, 0)).clicked() {
+            messages.push(Msg::SelectChord(chord.name.clone()));
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,423 @@
+// Standard MIDI Files. Songs are exported as their chord progression, for practising along in a DAW,
+// and recorded files are read back as channel messages for entering chords, see midi_input.rs.
+// Every chord in the sheet is played with the shape the song picked for it, the capo included,
+// and strummed by a pattern of eighth notes:
+//
+//   D  strum down, low string first
+//   U  strum up, high string first and a bit softer
+//   -  let the last strum ring
+//
+// The exported file has a tempo track and a guitar track with a marker at every chord change.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use crate::chord::chord_pitches;
+use crate::format::file_name;
+use crate::models::{Chord, Song};
+use crate::pitch::Key;
+use crate::symbol::ChordSymbol;
+
+// ticks are counted in u64 so long songs can't overflow, deltas are clamped when written
+const TICKS_PER_BEAT: u64 = 480;
+const EIGHTH: u64 = TICKS_PER_BEAT / 2;
+// between two strings of a strum
+const STRUM_SPREAD: u64 = 12;
+// largest delta a four byte variable length quantity holds
+const MAX_DELTA: u64 = 0x0fff_ffff;
+const DOWN_VELOCITY: u8 = 96;
+const UP_VELOCITY: u8 = 72;
+// general midi steel string acoustic guitar
+const GUITAR_PROGRAM: u8 = 25;
+// chords without a stored shape are played in close position from this note, C3
+const FALLBACK_ROOT: i32 = 48;
+
+#[derive(Serialize, Deserialize)]
+pub struct MidiSettings {
+    pub tempo: u32,
+    pub beats_per_bar: u8,
+    // 2, 4 or 8
+    pub beat_unit: u8,
+    pub beats_per_chord: u32,
+    pub pattern: String,
+    // file or directory the midi file is written to
+    pub path: String,
+    #[serde(skip)]
+    pub status: String
+}
+
+impl Default for MidiSettings {
+    fn default() -> Self {
+        MidiSettings {
+            tempo: 100,
+            beats_per_bar: 4,
+            beat_unit: 4,
+            beats_per_chord: 4,
+            pattern: "D-DU-UDU".to_owned(),
+            path: "".to_owned(),
+            status: "".to_owned()
+        }
+    }
+}
+
+// a folder gets a file named after the song
+pub fn export_path(song: &Song, chords: &[Chord], settings: &MidiSettings, path: &Path) -> io::Result<PathBuf> {
+    let file = match path.is_dir() {
+        true => path.join(format!("{}.mid", file_name(&song.name))),
+        false => path.to_path_buf()
+    };
+    fs::write(&file, song_midi(song, chords, settings))?;
+    Ok(file)
+}
+
+pub fn song_midi(song: &Song, chords: &[Chord], settings: &MidiSettings) -> Vec<u8> {
+    let sheet = song.sheet();
+    let key = song.sheet_key(&sheet);
+    let progression = sheet.chord_events()
+        .map(|event| (event.symbol.to_string(), voicing(song, key, chords, &event.symbol)))
+        .collect::<Vec<_>>();
+
+    let mut meta = Track::default();
+    meta.meta(0, 0x03, song.name.as_bytes());
+    meta.meta(0, 0x51, &(60_000_000 / settings.tempo.max(1)).to_be_bytes()[1..]);
+    let unit_power = (settings.beat_unit.max(1) as f32).log2() as u8;
+    meta.meta(0, 0x58, &[settings.beats_per_bar, unit_power, 24, 8]);
+
+    let mut guitar = Track::default();
+    guitar.meta(0, 0x03, b"Guitar");
+    guitar.event(0, &[0xc0, GUITAR_PROGRAM]);
+    let chord_length = settings.beats_per_chord.max(1) as u64 * TICKS_PER_BEAT;
+    let pattern = settings.pattern.chars().filter(|c| matches!(c.to_ascii_uppercase(), 'D' | 'U' | '-')).collect::<Vec<_>>();
+    for (idx, (name, pitches)) in progression.iter().enumerate() {
+        let start = idx as u64 * chord_length;
+        guitar.meta(start, 0x06, name.as_bytes());
+        // an empty pattern holds one strum for the whole chord
+        let strums = match pattern.is_empty() {
+            true => vec![(0, 'D')],
+            false => (0..chord_length / EIGHTH)
+                .map(|eighth| (eighth * EIGHTH, pattern[eighth as usize % pattern.len()].to_ascii_uppercase()))
+                .filter(|(_, stroke)| *stroke != '-')
+                .collect()
+        };
+        for (strum_idx, (offset, stroke)) in strums.iter().enumerate() {
+            let end = strums.get(strum_idx + 1).map(|(next, _)| *next).unwrap_or(chord_length);
+            let (order, velocity) = match stroke {
+                'U' => (pitches.iter().rev().copied().collect::<Vec<_>>(), UP_VELOCITY),
+                _ => (pitches.clone(), DOWN_VELOCITY)
+            };
+            for (string_idx, pitch) in order.iter().enumerate() {
+                let on = start + offset + string_idx as u64 * STRUM_SPREAD;
+                guitar.event(on.min(start + end - 1), &[0x90, *pitch, velocity]);
+                guitar.event(start + end, &[0x80, *pitch, 0]);
+            }
+        }
+    }
+
+    let mut bytes = vec![];
+    bytes.extend(b"MThd");
+    bytes.extend(6u32.to_be_bytes());
+    // format 1, two tracks
+    bytes.extend(1u16.to_be_bytes());
+    bytes.extend(2u16.to_be_bytes());
+    bytes.extend((TICKS_PER_BEAT as u16).to_be_bytes());
+    bytes.extend(meta.to_bytes());
+    bytes.extend(guitar.to_bytes());
+    bytes
+}
+
+// midi notes from low to high, from the shape the song picked or the tones of the symbol
+fn voicing(song: &Song, key: Option<Key>, chords: &[Chord], symbol: &ChordSymbol) -> Vec<u8> {
+    let shape_name = song.shape_name_in_key(&symbol.to_string(), key);
+    match song.preferred_chord(chords, &shape_name) {
+        Some(chord) => chord_pitches(&chord.notes, &chord.instrument.tuning, song.capo).iter()
+            .map(|pitch| pitch.midi().clamp(0, 127) as u8)
+            .collect(),
+        None => {
+            let root = FALLBACK_ROOT + symbol.root.pitch_class().semitone();
+            let bass = symbol.bass.map(|bass| FALLBACK_ROOT - 12 + bass.pitch_class().semitone());
+            bass.into_iter()
+                .chain(symbol.intervals().iter().map(|interval| root + interval))
+                .map(|note| note as u8)
+                .collect()
+        }
+    }
+}
+
+#[derive(Default)]
+struct Track {
+    // absolute tick and the event bytes, sorted when written
+    events: Vec<(u64, Vec<u8>)>
+}
+
+impl Track {
+    fn event(&mut self, tick: u64, bytes: &[u8]) {
+        self.events.push((tick, bytes.to_vec()));
+    }
+
+    fn meta(&mut self, tick: u64, kind: u8, data: &[u8]) {
+        let mut bytes = vec![0xff, kind];
+        bytes.extend(variable_length(data.len() as u32));
+        bytes.extend(data);
+        self.events.push((tick, bytes));
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut events = self.events.clone();
+        // stable, and note offs go before note ons at the same tick so repeated notes restart
+        events.sort_by_key(|(tick, bytes)| (*tick, bytes[0] & 0xf0 != 0x80));
+        let mut data = vec![];
+        let mut last = 0;
+        for (tick, bytes) in events.iter() {
+            data.extend(variable_length((tick - last).min(MAX_DELTA) as u32));
+            data.extend(bytes);
+            last = *tick;
+        }
+        data.extend([0x00, 0xff, 0x2f, 0x00]);
+
+        let mut bytes = vec![];
+        bytes.extend(b"MTrk");
+        bytes.extend((data.len() as u32).to_be_bytes());
+        bytes.extend(data);
+        bytes
+    }
+}
+
+// seven bits per byte, most significant first, the high bit set on all but the last
+fn variable_length(mut value: u32) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.insert(0, (value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes
+}
+
+// note and controller messages of every track in the order they are played, meta events and sysex are skipped
+pub fn read_messages(bytes: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let mut reader = Reader { bytes, at: 0 };
+    if reader.take(4)? != b"MThd" {
+        return Err("not a midi file".to_owned());
+    }
+    let header_length = reader.u32()? as usize;
+    reader.take(header_length)?;
+
+    let mut messages = vec![];
+    while reader.at < bytes.len() {
+        let kind = reader.take(4)?;
+        let length = reader.u32()? as usize;
+        let chunk = reader.take(length)?;
+        if kind == b"MTrk" {
+            messages.extend(track_messages(chunk)?);
+        }
+    }
+    // tracks are merged by time, stable so each keeps its own order
+    messages.sort_by_key(|(tick, _)| *tick);
+    Ok(messages.into_iter().map(|(_, message)| message).collect())
+}
+
+fn track_messages(track: &[u8]) -> Result<Vec<(u64, Vec<u8>)>, String> {
+    let mut reader = Reader { bytes: track, at: 0 };
+    let mut messages = vec![];
+    let mut tick = 0;
+    let mut running_status = None;
+    while reader.at < track.len() {
+        tick += reader.variable_length()? as u64;
+        let mut status = reader.u8()?;
+        match status {
+            // meta events and sysex cancel the running status
+            0xff => {
+                reader.u8()?;
+                let length = reader.variable_length()? as usize;
+                reader.take(length)?;
+                running_status = None;
+            }
+            0xf0 | 0xf7 => {
+                let length = reader.variable_length()? as usize;
+                reader.take(length)?;
+                running_status = None;
+            }
+            _ => {
+                // a data byte repeats the last status
+                if status < 0x80 {
+                    status = running_status.ok_or("data without a status byte")?;
+                    reader.at -= 1;
+                }
+                running_status = Some(status);
+                let data_length = match status & 0xf0 {
+                    0xc0 | 0xd0 => 1,
+                    _ => 2
+                };
+                let mut message = vec![status];
+                message.extend(reader.take(data_length)?);
+                messages.push((tick, message));
+            }
+        }
+    }
+    Ok(messages)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let taken = self.bytes.get(self.at..self.at + length).ok_or("the file ends too early")?;
+        self.at += length;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    // four bytes at most
+    fn variable_length(&mut self) -> Result<u32, String> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("a variable length quantity is longer than four bytes".to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::Instrument;
+
+    // events of every track with their absolute tick, the writer never uses running status
+    fn tracks(bytes: &[u8]) -> Vec<Vec<(u64, Vec<u8>)>> {
+        let mut tracks = vec![];
+        let mut at = 14;
+        while at < bytes.len() {
+            assert_eq!(&bytes[at..at + 4], b"MTrk");
+            let length = u32::from_be_bytes(bytes[at + 4..at + 8].try_into().unwrap()) as usize;
+            let data = &bytes[at + 8..at + 8 + length];
+            let (mut idx, mut tick, mut events) = (0, 0, vec![]);
+            while idx < data.len() {
+                let mut delta = 0;
+                loop {
+                    delta = (delta << 7) | (data[idx] & 0x7f) as u64;
+                    idx += 1;
+                    if data[idx - 1] & 0x80 == 0 {
+                        break;
+                    }
+                }
+                tick += delta;
+                let length = match data[idx] {
+                    0xff => 3 + data[idx + 2] as usize,
+                    status if status & 0xf0 == 0xc0 => 2,
+                    _ => 3
+                };
+                events.push((tick, data[idx..idx + length].to_vec()));
+                idx += length;
+            }
+            tracks.push(events);
+            at += 8 + length;
+        }
+        tracks
+    }
+
+    fn note_ons(track: &[(u64, Vec<u8>)]) -> Vec<(u64, u8, u8)> {
+        track.iter().filter(|(_, event)| event[0] == 0x90).map(|(tick, event)| (*tick, event[1], event[2])).collect()
+    }
+
+    fn note_offs(track: &[(u64, Vec<u8>)]) -> Vec<(u64, u8)> {
+        track.iter().filter(|(_, event)| event[0] == 0x80).map(|(tick, event)| (*tick, event[1])).collect()
+    }
+
+    fn song(text: &str) -> Song {
+        let mut song = Song::empty("la".to_owned());
+        song.text = text.to_owned();
+        song
+    }
+
+    #[test]
+    fn tempo_and_time_signature() {
+        let settings = MidiSettings { tempo: 120, beats_per_bar: 3, beat_unit: 8, ..Default::default() };
+        let bytes = song_midi(&song("C\nla"), &[], &settings);
+        assert_eq!(&bytes[..14], &[b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 2, 0x01, 0xe0]);
+        let tracks = tracks(&bytes);
+        assert_eq!(tracks.len(), 2);
+        // 500000 microseconds per beat, 3/8
+        assert!(tracks[0].contains(&(0, vec![0xff, 0x51, 3, 0x07, 0xa1, 0x20])));
+        assert!(tracks[0].contains(&(0, vec![0xff, 0x58, 4, 3, 3, 24, 8])));
+        assert!(tracks[1].contains(&(0, vec![0xc0, GUITAR_PROGRAM])));
+    }
+
+    #[test]
+    fn strum_pattern() {
+        let settings = MidiSettings { beats_per_chord: 2, pattern: "DU-".to_owned(), ..Default::default() };
+        let written = tracks(&song_midi(&song("C\nla"), &[], &settings));
+        assert_eq!(note_ons(&written[1]), vec![
+            (0, 48, DOWN_VELOCITY), (12, 52, DOWN_VELOCITY), (24, 55, DOWN_VELOCITY),
+            // up strums start on the high string
+            (240, 55, UP_VELOCITY), (252, 52, UP_VELOCITY), (264, 48, UP_VELOCITY),
+            // the pattern starts over on the fourth eighth
+            (720, 48, DOWN_VELOCITY), (732, 52, DOWN_VELOCITY), (744, 55, DOWN_VELOCITY)
+        ]);
+        // the hold lets the up strum ring until the next one
+        let ends = note_offs(&written[1]).iter().map(|(tick, _)| *tick).collect::<Vec<_>>();
+        assert_eq!(ends, vec![240, 240, 240, 720, 720, 720, 960, 960, 960]);
+
+        let settings = MidiSettings { beats_per_chord: 2, pattern: "".to_owned(), ..Default::default() };
+        let written = tracks(&song_midi(&song("C  G\nla la"), &[], &settings));
+        assert_eq!(note_ons(&written[1]).iter().map(|(tick, _, _)| *tick).collect::<Vec<_>>(), vec![0, 12, 24, 960, 972, 984]);
+        assert_eq!(note_offs(&written[1]).iter().map(|(tick, _)| *tick).collect::<Vec<_>>(), vec![960, 960, 960, 1920, 1920, 1920]);
+        assert!(written[1].contains(&(960, [vec![0xff, 0x06, 1], b"G".to_vec()].concat())));
+    }
+
+    #[test]
+    fn chords_without_a_shape_are_played_in_close_position() {
+        let settings = MidiSettings { pattern: "".to_owned(), ..Default::default() };
+        let pitches = |text: &str, chords: &[Chord]| note_ons(&tracks(&song_midi(&song(text), chords, &settings))[1])
+            .iter()
+            .map(|(_, note, _)| *note)
+            .collect::<Vec<_>>();
+        assert_eq!(pitches("Am7\nla", &[]), vec![57, 60, 64, 67]);
+        // the bass an octave below the root
+        assert_eq!(pitches("D/F#\nla", &[]), vec![42, 50, 54, 57]);
+
+        let open_c = Chord { notes: vec![(3, 5), (2, 4), (0, 3), (1, 2), (0, 1)], ..Chord::empty("C".to_owned(), Instrument::guitar()) };
+        assert_eq!(pitches("C\nla", &[open_c]), vec![48, 52, 55, 60, 64]);
+    }
+
+    fn file(track: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend(b"MThd");
+        bytes.extend([0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xe0]);
+        bytes.extend(b"MTrk");
+        bytes.extend((track.len() as u32).to_be_bytes());
+        bytes.extend(track);
+        bytes
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        // running status, a note off as a note on without velocity
+        let track = [0x00, 0x90, 40, 100, 0x10, 40, 0, 0x00, 0xff, 0x2f, 0x00];
+        assert_eq!(read_messages(&file(&track)), Ok(vec![vec![0x90, 40, 100], vec![0x90, 40, 0]]));
+        // deltas as large as they get add up past u32 without overflowing
+        let long_track = [[0xff, 0xff, 0xff, 0x7f, 0x90, 40, 100].as_slice(); 40].concat();
+        assert_eq!(read_messages(&file(&long_track)).unwrap().len(), 40);
+
+        assert!(read_messages(b"RIFF").is_err());
+        assert!(read_messages(&file(&[0x00, 0x90, 40])).is_err());
+        // five bytes of delta
+        assert!(read_messages(&file(&[0x81, 0x80, 0x80, 0x80, 0x00, 0x90, 40, 100])).is_err());
+        // a meta event ends the running status
+        assert!(read_messages(&file(&[0x00, 0x90, 40, 100, 0x00, 0xff, 0x01, 0x00, 0x00, 40, 0])).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+// Entering chords by playing them. Notes come from a MIDI guitar or keyboard, from the app's own
+// virtual port that other programs and loopback devices can send to, or from a recorded .mid file.
+// Everything pressed until all keys are let go is one chord, and it's put on the strings the easiest
+// way the tuning allows. Devices need the "midi" feature, files are always read.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use serde::{Serialize, Deserialize};
+use crate::chord::NotePos;
+use crate::instrument::Instrument;
+use crate::midi::read_messages;
+use crate::voicing::fit_shape;
+
+// name of the virtual port and of the app as a midi client
+#[cfg(feature = "midi")]
+const CLIENT_NAME: &str = "rusty-guitar";
+
+// notes from channel messages
+#[derive(Default)]
+pub struct NoteCapture {
+    held: BTreeSet<i32>,
+    // everything pressed since the last time nothing was held
+    chord: BTreeSet<i32>
+}
+
+impl NoteCapture {
+    // true when the message lets go of the last held note
+    pub fn message(&mut self, bytes: &[u8]) -> bool {
+        let (status, note, velocity) = match bytes {
+            [status, note, velocity, ..] => (status & 0xf0, *note as i32, *velocity),
+            _ => return false
+        };
+        match (status, velocity) {
+            (0x90, 1..) => {
+                if self.held.is_empty() {
+                    self.chord.clear();
+                }
+                self.held.insert(note);
+                self.chord.insert(note);
+                false
+            }
+            // note on with no velocity is a note off
+            (0x80, _) | (0x90, 0) => self.held.remove(&note) && self.held.is_empty(),
+            _ => false
+        }
+    }
+
+    // midi notes of the last chord, or the one still being played
+    pub fn notes(&self) -> Vec<i32> {
+        self.chord.iter().copied().collect()
+    }
+}
+
+// the first chord of a recorded file
+pub fn file_notes(path: &Path) -> Result<Vec<i32>, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let mut capture = NoteCapture::default();
+    for message in read_messages(&bytes)? {
+        if capture.message(&message) {
+            break;
+        }
+    }
+    match capture.notes().is_empty() {
+        true => Err("the file has no notes".to_owned()),
+        false => Ok(capture.notes())
+    }
+}
+
+// midi inputs and the proposed shape for the chords tab
+#[derive(Default, Serialize, Deserialize)]
+pub struct MidiInput {
+    pub is_open: bool,
+    pub instrument: Instrument,
+    // device port, the virtual port when there is none
+    pub port: Option<String>,
+    // recorded .mid file
+    pub path: String,
+    #[serde(skip)]
+    pub notes: Vec<i32>,
+    #[serde(skip)]
+    pub shape: Vec<NotePos>,
+    #[serde(skip)]
+    pub status: String,
+    #[serde(skip)]
+    capture: Arc<Mutex<NoteCapture>>,
+    #[cfg(feature = "midi")]
+    #[serde(skip)]
+    connection: Option<midir::MidiInputConnection<()>>
+}
+
+impl MidiInput {
+    pub fn load_file(&mut self) {
+        // the chord last played on a device would replace the file's on the next poll
+        *self.capture.lock().unwrap() = NoteCapture::default();
+        match file_notes(Path::new(&self.path)) {
+            Ok(notes) => self.propose(notes),
+            Err(error) => self.status = format!("can't read {}: {}", self.path, error)
+        }
+    }
+
+    // proposes the chord played on the device when it changed since the last call
+    pub fn poll(&mut self) {
+        let notes = self.capture.lock().unwrap().notes();
+        if !notes.is_empty() && notes != self.notes {
+            self.propose(notes);
+        }
+    }
+
+    pub fn propose(&mut self, notes: Vec<i32>) {
+        self.notes = notes;
+        match fit_shape(&self.notes, &self.instrument) {
+            Ok(shape) => {
+                self.shape = shape;
+                self.status.clear();
+            }
+            Err(error) => {
+                self.shape.clear();
+                self.status = error;
+            }
+        }
+    }
+
+    #[cfg(feature = "midi")]
+    pub fn ports() -> Result<Vec<String>, String> {
+        let input = midir::MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+        Ok(input.ports().iter().filter_map(|port| input.port_name(port).ok()).collect())
+    }
+
+    #[cfg(not(feature = "midi"))]
+    pub fn ports() -> Result<Vec<String>, String> {
+        Err("built without midi devices, enable the midi feature to play chords in".to_owned())
+    }
+
+    #[cfg(feature = "midi")]
+    pub fn is_listening(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    #[cfg(not(feature = "midi"))]
+    pub fn is_listening(&self) -> bool {
+        false
+    }
+
+    // notify is called from the midi thread after every message, to redraw
+    #[cfg(feature = "midi")]
+    pub fn listen(&mut self, notify: impl Fn() + Send + 'static) -> Result<(), String> {
+        self.stop();
+        let input = midir::MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+        let capture = Arc::clone(&self.capture);
+        let callback = move |_: u64, bytes: &[u8], _: &mut ()| {
+            capture.lock().unwrap().message(bytes);
+            notify();
+        };
+        let connection = match &self.port {
+            Some(name) => {
+                let port = input.ports().into_iter()
+                    .find(|port| input.port_name(port).as_ref() == Ok(name))
+                    .ok_or(format!("{} is not connected", name))?;
+                input.connect(&port, CLIENT_NAME, callback, ()).map_err(|e| e.to_string())?
+            }
+            None => virtual_port(input, callback)?
+        };
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "midi"))]
+    pub fn listen(&mut self, _notify: impl Fn() + Send + 'static) -> Result<(), String> {
+        Self::ports().map(|_| ())
+    }
+
+    pub fn stop(&mut self) {
+        #[cfg(feature = "midi")]
+        if let Some(connection) = self.connection.take() {
+            connection.close();
+        }
+        *self.capture.lock().unwrap() = NoteCapture::default();
+    }
+}
+
+#[cfg(all(feature = "midi", unix))]
+fn virtual_port(
+    input: midir::MidiInput,
+    callback: impl FnMut(u64, &[u8], &mut ()) + Send + 'static
+) -> Result<midir::MidiInputConnection<()>, String> {
+    use midir::os::unix::VirtualInput;
+    input.create_virtual(CLIENT_NAME, callback, ()).map_err(|e| e.to_string())
+}
+
+#[cfg(all(feature = "midi", not(unix)))]
+fn virtual_port(
+    _input: midir::MidiInput,
+    _callback: impl FnMut(u64, &[u8], &mut ()) + Send + 'static
+) -> Result<midir::MidiInputConnection<()>, String> {
+    Err("virtual ports aren't supported here, pick a device or use a loopback driver".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::{song_midi, MidiSettings};
+    use crate::models::Song;
+
+    fn note_on(note: u8) -> [u8; 3] {
+        [0x90, note, 100]
+    }
+
+    fn note_off(note: u8) -> [u8; 3] {
+        [0x80, note, 0]
+    }
+
+    #[test]
+    fn chord_ends_when_everything_is_released() {
+        let mut capture = NoteCapture::default();
+        assert!(!capture.message(&note_on(40)));
+        assert!(!capture.message(&note_on(47)));
+        assert!(!capture.message(&note_off(40)));
+        // pressed while another note is held, still the same chord
+        assert!(!capture.message(&note_on(52)));
+        assert!(!capture.message(&[0x90, 47, 0]));
+        assert!(capture.message(&note_off(52)));
+        assert_eq!(capture.notes(), vec![40, 47, 52]);
+
+        capture.message(&note_on(45));
+        assert_eq!(capture.notes(), vec![45]);
+        // controllers and short messages are ignored
+        assert!(!capture.message(&[0xb0, 64, 127]));
+        assert!(!capture.message(&[0xc0, 25]));
+    }
+
+    #[test]
+    fn played_notes_get_the_easiest_shape() {
+        let guitar = Instrument::guitar();
+        let open_e = fit_shape(&[40, 47, 52, 56, 59, 64], &guitar).unwrap();
+        assert_eq!(open_e, vec![(0, 6), (2, 5), (2, 4), (1, 3), (0, 2), (0, 1)]);
+        let open_c = fit_shape(&[64, 60, 55, 52, 48], &guitar).unwrap();
+        assert_eq!(open_c, vec![(3, 5), (2, 4), (0, 3), (1, 2), (0, 1)]);
+        assert!(fit_shape(&[30, 40], &guitar).is_err());
+        assert!(fit_shape(&[40, 41, 42, 43, 44, 45, 46], &guitar).is_err());
+    }
+
+    #[test]
+    fn exported_files_read_back() {
+        let mut song = Song::empty("la".to_owned());
+        song.text = "Am   F\nla la".to_owned();
+        let bytes = song_midi(&song, &[], &MidiSettings::default());
+        let mut capture = NoteCapture::default();
+        let first_strum_end = read_messages(&bytes).unwrap().iter().position(|message| capture.message(message));
+        assert!(first_strum_end.is_some());
+        // A minor in close position from A3
+        assert_eq!(capture.notes(), vec![57, 60, 64]);
+    }
+
+    #[test]
+    fn loaded_file_isnt_replaced_by_the_last_played_chord() {
+        let mut song = Song::empty("la".to_owned());
+        song.text = "Am\nla".to_owned();
+        let path = std::env::temp_dir().join(format!("rusty-guitar-{}.mid", crate::models::new_id()));
+        fs::write(&path, song_midi(&song, &[], &MidiSettings::default())).unwrap();
+
+        let mut input = MidiInput { instrument: Instrument::guitar(), ..Default::default() };
+        for message in [note_on(40), note_on(47), note_off(40), note_off(47)] {
+            input.capture.lock().unwrap().message(&message);
+        }
+        input.poll();
+        assert_eq!(input.notes, vec![40, 47]);
+        input.path = path.display().to_string();
+        input.load_file();
+        input.poll();
+        assert_eq!(input.notes, vec![57, 60, 64]);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,56 +1,289 @@
-use std::collections::HashMap;
-use std::fmt;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use itertools::Itertools;
 use serde::{Serialize, Deserialize};
 use crate::chord::NotePos;
+use crate::instrument::Instrument;
+use crate::pitch::Key;
+use crate::sheet::SongSheet;
+use crate::symbol::ChordSymbol;
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Note {
-    A, B, C, D, E, F, G
-}
+pub type Id = u64;
 
-impl Note {
-    pub fn all() -> [Note; 7] {
-        use Note::*;
-        return [A, B, C, D, E, F, G];
-    }
-}
+static LAST_ID: AtomicU64 = AtomicU64::new(0);
+static SALT: OnceLock<u64> = OnceLock::new();
 
-impl fmt::Display for Note {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+// milliseconds since the epoch in the high bits and a random value picked once per run in the
+// low 16, so ids made by different runs or machines don't meet. Within a run they only grow.
+pub fn new_id() -> Id {
+    let salt = *SALT.get_or_init(|| RandomState::new().build_hasher().finish() & 0xffff);
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    let mut last = LAST_ID.load(Ordering::Relaxed);
+    loop {
+        let id = ((millis << 16) | salt).max(last + 1);
+        match LAST_ID.compare_exchange(last, id, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return id,
+            Err(current) => last = current
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Chord {
-    pub id: i32,
+    pub id: Id,
     pub name: String,
-    pub notes: Vec<NotePos>
+    pub notes: Vec<NotePos>,
+    #[serde(default)]
+    pub instrument: Instrument,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    // 1 (easy) to 5, None until rated
+    #[serde(default)]
+    pub difficulty: Option<u8>
 }
 
+pub const MAX_DIFFICULTY: u8 = 5;
+
 impl Chord {
-    pub fn empty(id: i32, name: String) -> Self {
+    pub fn empty(name: String, instrument: Instrument) -> Self {
         Self {
-            id,
+            id: new_id(),
             name,
-            notes: vec![]
+            notes: vec![],
+            instrument,
+            tags: vec![],
+            favorite: false,
+            difficulty: None
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Song {
+    // files written before songs had ids get one when they are read
+    #[serde(default = "new_id")]
+    pub(crate) id: Id,
     pub(crate) name: String,
     pub(crate) text: String,
-    pub(crate) preferences: HashMap<String, i32>
+    // shape name to the id of the chord picked for it
+    pub(crate) preferences: HashMap<String, Id>,
+    // instrument new chords created from the song are made for
+    #[serde(default)]
+    pub(crate) instrument: Instrument,
+    // chords in the text are the sounding ones, shapes are played relative to the capo
+    #[serde(default)]
+    pub(crate) capo: i32,
+    #[serde(default)]
+    pub(crate) artist: String,
+    // key set by hand or imported, otherwise it is guessed from the chords
+    #[serde(default)]
+    pub(crate) key: Option<Key>,
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+    // kept but left out of the song list unless archived songs are shown
+    #[serde(default)]
+    pub(crate) archived: bool
 }
 
 impl Song {
     pub(crate) fn empty(name: String) -> Self {
         Song {
+            id: new_id(),
             name,
             text: "".to_owned(),
-            preferences: HashMap::new()
+            preferences: HashMap::new(),
+            instrument: Instrument::default(),
+            capo: 0,
+            artist: "".to_owned(),
+            key: None,
+            tags: vec![],
+            archived: false
         }
     }
-}
\ No newline at end of file
+
+    // chord lines are rewritten in place, preferences stay keyed by the old names
+    // so the picked voicings come back when the song is transposed back
+    // a guessed key isn't stored, it moves with the first chord it was guessed from
+    pub(crate) fn transpose(&mut self, semitones: i32) {
+        let prefer_flats = self.key()
+            .map(|key| key.transpose(semitones).prefers_flats())
+            .unwrap_or(false);
+        let mut sheet = self.sheet();
+        sheet.transpose(semitones, prefer_flats);
+        self.text = sheet.to_text();
+        self.key = self.key.map(|key| key.transpose(semitones));
+    }
+
+    pub(crate) fn key(&self) -> Option<Key> {
+        self.sheet_key(&self.sheet())
+    }
+
+    // the key with the sheet already parsed
+    pub(crate) fn sheet_key(&self, sheet: &SongSheet) -> Option<Key> {
+        self.key.or_else(|| sheet.guess_key())
+    }
+
+    pub(crate) fn sheet(&self) -> SongSheet {
+        SongSheet::parse(&self.text)
+    }
+
+    // name of the shape played for a sounding chord with the capo on
+    pub(crate) fn shape_name(&self, sounding: &str) -> String {
+        match self.capo {
+            0 => sounding.to_owned(),
+            _ => self.shape_name_in_key(sounding, self.key())
+        }
+    }
+
+    // the same with the song key worked out once by the caller, for naming every chord of a sheet
+    pub(crate) fn shape_name_in_key(&self, sounding: &str, key: Option<Key>) -> String {
+        if self.capo == 0 {
+            return sounding.to_owned();
+        }
+        match ChordSymbol::parse(sounding) {
+            Ok(symbol) => {
+                let prefer_flats = key
+                    .map(|key| key.transpose(-self.capo).prefers_flats())
+                    .unwrap_or(false);
+                symbol.transpose(-self.capo, prefer_flats).to_string()
+            }
+            Err(_) => sounding.to_owned()
+        }
+    }
+
+    // voicing picked for a shape in this song, the first one in the library otherwise
+    pub(crate) fn preferred_chord<'a>(&self, chords: &'a [Chord], shape_name: &str) -> Option<&'a Chord> {
+        let preference = self.preferences.get(shape_name);
+        let candidates = chords.iter().filter(|chord| chord.name == shape_name).sorted_by_key(|c| c.id).collect::<Vec<_>>();
+        candidates.iter()
+            .find(|chord| preference == Some(&chord.id))
+            .or(candidates.first())
+            .copied()
+    }
+
+    // preferred voicings that aren't among the chords anymore, the song falls back to another one
+    pub(crate) fn missing_preferences(&self, chords: &[Chord]) -> Vec<(&str, Id)> {
+        self.preferences.iter()
+            .filter(|(_, id)| !chords.iter().any(|chord| chord.id == **id))
+            .map(|(name, id)| (name.as_str(), *id))
+            .sorted()
+            .collect()
+    }
+
+    // sounding chord names paired with the shapes played for them
+    pub(crate) fn capo_chart(&self) -> Vec<(String, String)> {
+        let sheet = self.sheet();
+        let key = self.sheet_key(&sheet);
+        sheet.chords().iter()
+            .map(|symbol| (symbol.to_string(), self.shape_name_in_key(&symbol.to_string(), key)))
+            .collect()
+    }
+}
+
+// another song already has the name, ignoring case and surrounding spaces
+pub fn song_name_taken(songs: &[Song], id: Id, name: &str) -> bool {
+    let name = name.trim().to_lowercase();
+    songs.iter().any(|song| song.id != id && song.name.trim().to_lowercase() == name)
+}
+
+// gives a new id to every chord or song that repeats an id seen before it, for data written
+// before ids were unique, true when anything changed. Like the migration in persistence.rs
+// a preference goes to the first chord with its name and old id.
+pub fn fix_duplicate_ids(chords: &mut [Chord], songs: &mut [Song]) -> bool {
+    let mut changed = false;
+    let mut seen = HashSet::new();
+    let mut chord_ids = HashMap::new();
+    for chord in chords.iter_mut() {
+        let old = chord.id;
+        if !seen.insert(chord.id) {
+            chord.id = new_id();
+            changed = true;
+        }
+        chord_ids.entry((chord.name.clone(), old)).or_insert(chord.id);
+    }
+    for song in songs.iter_mut() {
+        for (name, id) in song.preferences.iter_mut() {
+            if let Some(new) = chord_ids.get(&(name.clone(), *id)) {
+                *id = *new;
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    for song in songs.iter_mut() {
+        if !seen.insert(song.id) {
+            song.id = new_id();
+            changed = true;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(text: &str) -> Song {
+        let mut song = Song::empty("la".to_owned());
+        song.text = text.to_owned();
+        song
+    }
+
+    #[test]
+    fn transposing_back_keeps_the_spelling() {
+        let cases = [
+            ("F  Bb  C7\nla la la", 1),
+            ("F  Bb  C7\nla la la", -1),
+            ("E  A#m  B\nla la la", 1),
+            // an octave keeps even the spelling the key wouldn't use
+            ("F  A#  C\nla la la", 12),
+            ("F  A#  C\nla la la", -12)
+        ];
+        for (text, shift) in cases {
+            let mut transposed = song(text);
+            transposed.transpose(shift);
+            transposed.transpose(-shift);
+            assert_eq!(transposed.text, text, "{} by {}", text, shift);
+        }
+        let mut up = song("F  Bb  C7\nla la la");
+        up.transpose(1);
+        assert_eq!(up.text, "F# B   C#7\nla la la");
+        // the key is guessed for the spelling, not stored
+        assert_eq!(up.key, None);
+        up.key = "F#".parse().ok();
+        up.transpose(-1);
+        assert_eq!(up.key, "F".parse().ok());
+    }
+
+    #[test]
+    fn chord_lines_with_bars_and_repeats_are_transposed() {
+        let mut song = song("|: G    C :| x2\nla la la\nN.C.   Em  /  / |\nso long\n% (x3)");
+        song.transpose(2);
+        assert_eq!(song.text, "|: A    D :| x2\nla la la\nN.C.   F#m /  / |\nso long\n% (x3)");
+        song.transpose(-2);
+        assert_eq!(song.text, "|: G    C :| x2\nla la la\nN.C.   Em  /  / |\nso long\n% (x3)");
+    }
+
+    #[test]
+    fn duplicate_chord_ids_keep_their_preferences() {
+        let first = Chord::empty("C".to_owned(), Instrument::guitar());
+        let mut second = Chord::empty("G".to_owned(), Instrument::guitar());
+        second.id = first.id;
+        let mut song = song("C G\nla la");
+        song.preferences.insert("C".to_owned(), first.id);
+        song.preferences.insert("G".to_owned(), first.id);
+        let mut chords = vec![first, second];
+        let mut songs = vec![song];
+        assert!(fix_duplicate_ids(&mut chords, &mut songs));
+        assert_ne!(chords[0].id, chords[1].id);
+        assert_eq!(songs[0].preferences["C"], chords[0].id);
+        assert_eq!(songs[0].preferences["G"], chords[1].id);
+        assert!(!fix_duplicate_ids(&mut chords, &mut songs));
+    }
+}
@@ -0,0 +1,318 @@
+// The whole neck up to the 24th fret, for seeing where a scale, the tones of a chord or its CAGED
+// shapes lie on every string. Marks are worked out from the tuning, so any instrument works;
+// CAGED shapes are guitar shapes and need strings tuned in standard intervals.
+
+use eframe::egui::*;
+use serde::{Serialize, Deserialize};
+use crate::chord::NotePos;
+use crate::instrument::Instrument;
+use crate::pitch::{Interval, PitchClass};
+use crate::scale::Scale;
+use crate::symbol::ChordSymbol;
+
+pub const NECK_FRETS: i32 = 24;
+const FRET_WIDTH: f32 = 42.0;
+const STRING_SPACING: f32 = 22.0;
+// the open string column before the nut
+const OPEN_WIDTH: f32 = 32.0;
+const TOP_PADDING: f32 = 15.0;
+const BOTTOM_PADDING: f32 = 25.0;
+const RIGHT_PADDING: f32 = 10.0;
+const NUT_THICKNESS: f32 = 4.0;
+const MARK_RADIUS: f32 = 9.5;
+const INLAY_RADIUS: f32 = 4.0;
+const LABEL_FONT_SIZE: f32 = 11.0;
+const FRET_FONT_SIZE: f32 = 12.0;
+const INLAYS: [i32; 10] = [3, 5, 7, 9, 12, 15, 17, 19, 21, 24];
+// semitones between neighbouring strings of a guitar in standard tuning, lowest first
+const STANDARD_INTERVALS: [i32; 5] = [5, 5, 5, 4, 5];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Overlay {
+    #[default]
+    Scale,
+    ChordTones,
+    Caged
+}
+
+impl Overlay {
+    pub fn all() -> [Overlay; 3] {
+        [Overlay::Scale, Overlay::ChordTones, Overlay::Caged]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Overlay::Scale => "scale",
+            Overlay::ChordTones => "chord tones",
+            Overlay::Caged => "CAGED"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Labels {
+    #[default]
+    Notes,
+    // intervals above the scale or chord root
+    Degrees
+}
+
+// the five open chord shapes in the order they follow each other up the neck
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CagedShape {
+    C,
+    A,
+    G,
+    E,
+    D
+}
+
+impl CagedShape {
+    pub fn all() -> [CagedShape; 5] {
+        [CagedShape::C, CagedShape::A, CagedShape::G, CagedShape::E, CagedShape::D]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CagedShape::C => "C",
+            CagedShape::A => "A",
+            CagedShape::G => "G",
+            CagedShape::E => "E",
+            CagedShape::D => "D"
+        }
+    }
+
+    // the open chord in standard tuning and the note its root is played on
+    fn open_shape(&self, minor: bool) -> (NotePos, &'static [NotePos]) {
+        match (self, minor) {
+            (CagedShape::C, false) => ((3, 5), &[(3, 5), (2, 4), (0, 3), (1, 2), (0, 1)]),
+            (CagedShape::C, true) => ((3, 5), &[(3, 5), (1, 4), (0, 3), (1, 2), (3, 1)]),
+            (CagedShape::A, false) => ((0, 5), &[(0, 5), (2, 4), (2, 3), (2, 2), (0, 1)]),
+            (CagedShape::A, true) => ((0, 5), &[(0, 5), (2, 4), (2, 3), (1, 2), (0, 1)]),
+            (CagedShape::G, false) => ((3, 6), &[(3, 6), (2, 5), (0, 4), (0, 3), (0, 2), (3, 1)]),
+            (CagedShape::G, true) => ((3, 6), &[(3, 6), (1, 5), (0, 4), (0, 3), (3, 2), (3, 1)]),
+            (CagedShape::E, false) => ((0, 6), &[(0, 6), (2, 5), (2, 4), (1, 3), (0, 2), (0, 1)]),
+            (CagedShape::E, true) => ((0, 6), &[(0, 6), (2, 5), (2, 4), (0, 3), (0, 2), (0, 1)]),
+            (CagedShape::D, false) => ((0, 4), &[(0, 4), (2, 3), (3, 2), (2, 1)]),
+            (CagedShape::D, true) => ((0, 4), &[(0, 4), (2, 3), (3, 2), (1, 1)])
+        }
+    }
+}
+
+// neck inputs for the chords tab, the chord is the selected one
+#[derive(Default, Serialize, Deserialize)]
+pub struct NeckView {
+    pub is_open: bool,
+    pub instrument: Instrument,
+    pub overlay: Overlay,
+    pub scale: Scale,
+    pub scale_root: PitchClass,
+    // None shows all five
+    pub caged_shape: Option<CagedShape>,
+    pub labels: Labels
+}
+
+pub struct NeckMark {
+    pub note: NotePos,
+    pub label: String,
+    pub is_root: bool,
+    pub shape: Option<CagedShape>
+}
+
+impl NeckView {
+    // marks on every string up to the last fret of the instrument
+    pub fn marks(&self, chord: Option<&ChordSymbol>) -> Result<Vec<NeckMark>, String> {
+        match self.overlay {
+            Overlay::Scale => {
+                let prefer_flats = self.scale.key(self.scale_root).prefers_flats();
+                let marks = self.positions()
+                    .filter_map(|note| {
+                        let tone = self.instrument.tuning.pitch_at(note)?.pitch_class();
+                        self.scale.contains(self.scale_root, tone).then(|| {
+                            let interval = self.scale_root.interval_to(tone);
+                            let label = match self.labels {
+                                Labels::Notes => tone.spell(prefer_flats).to_string(),
+                                Labels::Degrees => interval.short_name().to_owned()
+                            };
+                            NeckMark { note, label, is_root: interval.semitones() == 0, shape: None }
+                        })
+                    })
+                    .collect();
+                Ok(marks)
+            }
+            Overlay::ChordTones => {
+                let chord = chord.ok_or("select a chord to see its tones")?;
+                Ok(self.positions().filter_map(|note| self.chord_mark(chord, note, None)).collect())
+            }
+            Overlay::Caged => self.caged_marks(chord.ok_or("select a chord to see its CAGED shapes")?)
+        }
+    }
+
+    fn positions(&self) -> impl Iterator<Item = NotePos> + '_ {
+        let last_fret = self.instrument.frets.min(NECK_FRETS);
+        (1..=self.instrument.string_count()).flat_map(move |string| (0..=last_fret).map(move |fret| (fret, string)))
+    }
+
+    fn chord_mark(&self, chord: &ChordSymbol, note: NotePos, shape: Option<CagedShape>) -> Option<NeckMark> {
+        let tone = self.instrument.tuning.pitch_at(note)?.pitch_class();
+        let root = chord.root.pitch_class();
+        let degree = chord.degrees().into_iter().find(|(_, semitones)| root.transpose(*semitones) == tone);
+        let is_bass = chord.bass.is_some_and(|bass| bass.pitch_class() == tone);
+        if degree.is_none() && !is_bass {
+            return None;
+        }
+        let label = match self.labels {
+            Labels::Notes => chord.root.transpose(root.interval_to(tone).semitones(), chord.root.accidental < 0).to_string(),
+            Labels::Degrees => degree.map(|(degree, semitones)| degree_name(degree, semitones))
+                .unwrap_or_else(|| root.interval_to(tone).short_name().to_owned())
+        };
+        Some(NeckMark { note, label, is_root: tone == root, shape })
+    }
+
+    // the open shapes moved up to the chord's root, each once from its lowest position and again an octave up
+    fn caged_marks(&self, chord: &ChordSymbol) -> Result<Vec<NeckMark>, String> {
+        let tuning = &self.instrument.tuning;
+        let intervals = tuning.strings.windows(2).map(|pair| pair[1].midi() - pair[0].midi()).collect::<Vec<_>>();
+        if intervals != STANDARD_INTERVALS {
+            return Err("CAGED shapes need six strings in standard intervals".to_owned());
+        }
+        let chord_intervals = chord.intervals();
+        let minor = match (chord_intervals.contains(&4), chord_intervals.contains(&3)) {
+            (true, _) => false,
+            (false, true) => true,
+            _ => return Err("CAGED shapes are major or minor chords".to_owned())
+        };
+
+        let mut marks = vec![];
+        let shapes = CagedShape::all().into_iter().filter(|shape| self.caged_shape.is_none_or(|picked| picked == *shape));
+        for shape in shapes {
+            let (root_note, notes) = shape.open_shape(minor);
+            let shape_root = tuning.pitch_at(root_note).unwrap().pitch_class();
+            let shift = shape_root.interval_to(chord.root.pitch_class()).semitones();
+            for octave in [0, 12] {
+                let moved = notes.iter().map(|(fret, string)| (fret + shift + octave, *string)).collect::<Vec<_>>();
+                if moved.iter().all(|note| self.instrument.contains(*note) && note.0 <= NECK_FRETS) {
+                    marks.extend(moved.into_iter().filter_map(|note| self.chord_mark(chord, note, Some(shape))));
+                }
+            }
+        }
+        Ok(marks)
+    }
+}
+
+// extensions past the octave keep their name, b9 rather than b2
+fn degree_name(degree: u8, semitones: i32) -> String {
+    let natural = match degree {
+        9 => 2,
+        11 => 5,
+        13 => 9,
+        _ => return Interval(semitones).short_name().to_owned()
+    };
+    let accidental = match semitones - natural {
+        -1 => "b",
+        1 => "#",
+        _ => ""
+    };
+    format!("{}{}", accidental, degree)
+}
+
+// string 1 on top like the chord diagrams, hovering shows the pitch under the pointer
+pub fn draw_neck(ui: &mut Ui, instrument: &Instrument, marks: &[NeckMark]) -> Response {
+    let string_count = instrument.string_count();
+    let nut_x = OPEN_WIDTH;
+    let width = nut_x + NECK_FRETS as f32 * FRET_WIDTH + RIGHT_PADDING;
+    let height = TOP_PADDING + (string_count - 1) as f32 * STRING_SPACING + BOTTOM_PADDING;
+    let (response, painter) = ui.allocate_painter(Vec2::new(width, height), Sense::hover());
+    let origin = response.rect.min;
+    let point = |x: f32, y: f32| origin + Vec2::new(x, y);
+    let string_y = |string: i32| TOP_PADDING + (string - 1) as f32 * STRING_SPACING;
+    let note_x = |fret: i32| if fret == 0 { nut_x / 2.0 } else { nut_x + (fret as f32 - 0.5) * FRET_WIDTH };
+
+    let visuals = ui.visuals();
+    let color = visuals.text_color();
+    let weak = visuals.weak_text_color();
+    let bottom = string_y(string_count);
+
+    // inlays between the middle strings, two on the octaves
+    let middle = (TOP_PADDING + bottom) / 2.0;
+    for fret in INLAYS {
+        let ys = if fret % 12 == 0 { vec![middle - STRING_SPACING, middle + STRING_SPACING] } else { vec![middle] };
+        for y in ys {
+            painter.circle_filled(point(note_x(fret), y), INLAY_RADIUS, visuals.faint_bg_color.gamma_multiply(4.0));
+        }
+    }
+
+    painter.line_segment([point(nut_x, TOP_PADDING), point(nut_x, bottom)], Stroke::new(NUT_THICKNESS, color));
+    for fret in 1..=NECK_FRETS {
+        // frets the instrument doesn't have are drawn faintly
+        let fret_color = if fret <= instrument.frets { color } else { weak };
+        let x = nut_x + fret as f32 * FRET_WIDTH;
+        painter.line_segment([point(x, TOP_PADDING), point(x, bottom)], Stroke::new(1.0, fret_color));
+        painter.text(point(note_x(fret), height - 9.0), Align2::CENTER_CENTER, fret.to_string(), FontId::proportional(FRET_FONT_SIZE), weak);
+    }
+    for string in 1..=string_count {
+        let y = string_y(string);
+        painter.line_segment([point(nut_x, y), point(width - RIGHT_PADDING, y)], Stroke::new(1.0, color));
+    }
+
+    for mark in marks {
+        let center = point(note_x(mark.note.0), string_y(mark.note.1));
+        let fill = match (mark.shape, mark.is_root) {
+            (Some(shape), _) => shape_color(shape),
+            (None, true) => visuals.selection.bg_fill,
+            (None, false) => visuals.widgets.inactive.bg_fill
+        };
+        let stroke = if mark.is_root { Stroke::new(2.0, color) } else { Stroke::new(1.0, weak) };
+        painter.circle(center, MARK_RADIUS, fill, stroke);
+        painter.text(center, Align2::CENTER_CENTER, &mark.label, FontId::proportional(LABEL_FONT_SIZE), visuals.strong_text_color());
+    }
+
+    let hovered = response.hover_pos().and_then(|pos| {
+        let (x, y) = (pos.x - origin.x, pos.y - origin.y);
+        let string = ((y - TOP_PADDING) / STRING_SPACING).round() as i32 + 1;
+        let fret = if x < nut_x { 0 } else { ((x - nut_x) / FRET_WIDTH).floor() as i32 + 1 };
+        instrument.tuning.pitch_at((fret, string)).filter(|_| instrument.contains((fret, string)))
+            .map(|pitch| format!("{}, string {} fret {}", pitch, string, fret))
+    });
+    match hovered {
+        Some(text) => response.on_hover_text_at_pointer(text),
+        None => response
+    }
+}
+
+fn shape_color(shape: CagedShape) -> Color32 {
+    match shape {
+        CagedShape::C => Color32::from_rgb(214, 96, 77),
+        CagedShape::A => Color32::from_rgb(224, 170, 60),
+        CagedShape::G => Color32::from_rgb(110, 170, 80),
+        CagedShape::E => Color32::from_rgb(70, 140, 200),
+        CagedShape::D => Color32::from_rgb(150, 100, 190)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use super::*;
+
+    // labels of the chord tones on the first five frets of the low e string and the one above
+    fn degree_labels(chord: &str) -> Vec<String> {
+        let view = NeckView { overlay: Overlay::ChordTones, labels: Labels::Degrees, instrument: Instrument::guitar(), ..Default::default() };
+        let chord = ChordSymbol::parse(chord).unwrap();
+        view.marks(Some(&chord)).unwrap().into_iter()
+            .filter(|mark| mark.note.1 >= 5 && mark.note.0 <= 5)
+            .sorted_by_key(|mark| (-mark.note.1, mark.note.0))
+            .map(|mark| mark.label)
+            .collect()
+    }
+
+    #[test]
+    fn extensions_keep_their_degree() {
+        // A A# B C C# D on the A string, E F F# G G# A on the low E
+        assert_eq!(degree_labels("C9"), vec!["3", "5", "b7", "1", "9"]);
+        assert_eq!(degree_labels("C13"), vec!["3", "11", "5", "13", "13", "b7", "1", "9"]);
+        assert_eq!(degree_labels("C7b9#11"), vec!["3", "#11", "5", "b7", "1", "b9"]);
+        assert_eq!(degree_labels("Cadd2"), vec!["3", "5", "1", "2"]);
+    }
+}
@@ -0,0 +1,204 @@
+// Minimal pdf writer: a4 pages, the standard Type1 fonts and stroked lines and circles.
+// Enough to print songs without external tools. Coordinates are measured from the
+// top left corner like on screen and flipped when written.
+
+use crate::format::number;
+
+pub const PAGE_WIDTH: f32 = 595.0;
+pub const PAGE_HEIGHT: f32 = 842.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Font {
+    Helvetica,
+    HelveticaBold,
+    HelveticaOblique,
+    Courier,
+    CourierBold
+}
+
+impl Font {
+    const ALL: [Font; 5] = [Font::Helvetica, Font::HelveticaBold, Font::HelveticaOblique, Font::Courier, Font::CourierBold];
+
+    fn base_name(&self) -> &'static str {
+        match self {
+            Font::Helvetica => "Helvetica",
+            Font::HelveticaBold => "Helvetica-Bold",
+            Font::HelveticaOblique => "Helvetica-Oblique",
+            Font::Courier => "Courier",
+            Font::CourierBold => "Courier-Bold"
+        }
+    }
+
+    fn resource(&self) -> String {
+        format!("F{}", Font::ALL.iter().position(|f| f == self).unwrap() + 1)
+    }
+
+    // courier is monospaced, helvetica uses rounded widths of the common glyphs
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        let em = match self {
+            Font::Courier | Font::CourierBold => text.chars().count() as f32 * 0.6,
+            _ => text.chars().map(helvetica_width).sum()
+        };
+        em * size
+    }
+}
+
+fn helvetica_width(c: char) -> f32 {
+    match c {
+        'i' | 'j' | 'l' | '\'' | '|' => 0.22,
+        ' ' | 'I' | 'f' | 't' | '.' | ',' | ':' | ';' | '!' | '/' | '(' | ')' | '[' | ']' => 0.28,
+        'r' | '-' => 0.33,
+        'm' | 'M' => 0.83,
+        'w' | 'W' => 0.78,
+        'A'..='Z' => 0.67,
+        _ => 0.56
+    }
+}
+
+#[derive(Default)]
+pub struct Page {
+    content: String
+}
+
+impl Page {
+    // y is the baseline of the text
+    pub fn text(&mut self, x: f32, y: f32, font: Font, size: f32, text: &str) {
+        self.content.push_str(&format!(
+            "BT /{} {} Tf {} {} Td ({}) Tj ET\n",
+            font.resource(), number(size), number(x), number(PAGE_HEIGHT - y), escape(text)
+        ));
+    }
+
+    pub fn line(&mut self, from: (f32, f32), to: (f32, f32), width: f32) {
+        self.content.push_str(&format!(
+            "{} w {} {} m {} {} l S\n",
+            number(width), number(from.0), number(PAGE_HEIGHT - from.1), number(to.0), number(PAGE_HEIGHT - to.1)
+        ));
+    }
+
+    // white inside so lines under it are hidden, four bezier quarters
+    pub fn circle(&mut self, center: (f32, f32), radius: f32, width: f32) {
+        let (x, y) = (center.0, PAGE_HEIGHT - center.1);
+        let k = radius * 0.5523;
+        let mut path = format!("{} w 1 g {} {} m\n", number(width), number(x + radius), number(y));
+        let quarters = [
+            (x + radius, y + k, x + k, y + radius, x, y + radius),
+            (x - k, y + radius, x - radius, y + k, x - radius, y),
+            (x - radius, y - k, x - k, y - radius, x, y - radius),
+            (x + k, y - radius, x + radius, y - k, x + radius, y)
+        ];
+        for (x1, y1, x2, y2, x3, y3) in quarters {
+            path.push_str(&format!(
+                "{} {} {} {} {} {} c\n",
+                number(x1), number(y1), number(x2), number(y2), number(x3), number(y3)
+            ));
+        }
+        path.push_str("B 0 g\n");
+        self.content.push_str(&path);
+    }
+}
+
+#[derive(Default)]
+pub struct PdfDocument {
+    pub pages: Vec<Page>
+}
+
+impl PdfDocument {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let font_ids = (0..Font::ALL.len()).map(|i| 3 + i).collect::<Vec<_>>();
+        let first_page_id = 3 + Font::ALL.len();
+        let page_ids = (0..self.pages.len()).map(|i| first_page_id + i * 2).collect::<Vec<_>>();
+
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+                self.pages.len()
+            )
+        ];
+        for font in Font::ALL {
+            objects.push(format!("<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>", font.base_name()));
+        }
+        let font_resources = Font::ALL.iter().zip(font_ids.iter())
+            .map(|(font, id)| format!("/{} {} 0 R", font.resource(), id))
+            .collect::<Vec<_>>()
+            .join(" ");
+        for (page, id) in self.pages.iter().zip(page_ids.iter()) {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << {} >> >> /Contents {} 0 R >>",
+                number(PAGE_WIDTH), number(PAGE_HEIGHT), font_resources, id + 1
+            ));
+            objects.push(format!("<< /Length {} >>\nstream\n{}endstream", page.content.len(), page.content));
+        }
+
+        // everything written is ascii, so string lengths are byte offsets
+        let mut output = "%PDF-1.4\n".to_owned();
+        let mut offsets = vec![];
+        for (idx, object) in objects.iter().enumerate() {
+            offsets.push(output.len());
+            output.push_str(&format!("{} 0 obj\n{}\nendobj\n", idx + 1, object));
+        }
+        let xref = output.len();
+        output.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+        for offset in offsets {
+            output.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        output.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref));
+        output.into_bytes()
+    }
+}
+
+// latin-1 characters map to the same WinAnsi codes and are written as octal escapes,
+// anything else the standard fonts can't show becomes "?"
+fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                result.push('\\');
+                result.push(c);
+            }
+            ' '..='~' => result.push(c),
+            '\u{a0}'..='\u{ff}' => result.push_str(&format!("\\{:03o}", c as u32)),
+            '♯' => result.push('#'),
+            '♭' => result.push('b'),
+            '\t' => result.push(' '),
+            _ => result.push('?')
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xref_points_at_every_object() {
+        let mut document = PdfDocument::default();
+        for text in ["one", "two (2)"] {
+            let mut page = Page::default();
+            page.text(50.0, 50.0, Font::Helvetica, 10.0, text);
+            page.line((0.0, 0.0), (10.0, 10.0), 1.0);
+            document.pages.push(page);
+        }
+        let output = String::from_utf8(document.to_bytes()).unwrap();
+        assert!(output.starts_with("%PDF-1.4\n"));
+        assert!(output.ends_with("%%EOF\n"));
+
+        let startxref = output.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse::<usize>().unwrap();
+        assert!(output[startxref..].starts_with("xref\n"));
+        let mut xref = output[startxref..].lines().skip(1);
+        let count = xref.next().unwrap().strip_prefix("0 ").unwrap().parse::<usize>().unwrap();
+        // catalog, pages, the fonts and a page and its contents for every page
+        assert_eq!(count, 1 + 2 + Font::ALL.len() + 2 * 2);
+        assert_eq!(xref.next(), Some("0000000000 65535 f "));
+        for id in 1..count {
+            let offset = xref.next().unwrap()[..10].parse::<usize>().unwrap();
+            assert!(output[offset..].starts_with(&format!("{} 0 obj\n", id)), "object {}", id);
+        }
+        assert!(output.contains("/Count 2"));
+        assert!(output.contains("(two \\(2\\)) Tj"));
+    }
+}
@@ -0,0 +1,265 @@
+// Loading the state saved in the app storage. The json carries a schema version and older
+// versions are migrated step by step. A blob that still doesn't load is written to a backup
+// file before anything can overwrite it, and whatever parsed of it is offered for recovery.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use crate::models::{new_id, Chord, Song};
+use crate::state::State;
+
+pub const SCHEMA_VERSION: u64 = 2;
+const APP_NAME: &str = "MyGuitar";
+
+// MIGRATIONS[n] takes a blob saved with version n to version n + 1
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[
+    migrate_unversioned,
+    migrate_ids
+];
+
+pub struct LoadError {
+    pub message: String,
+    pub backup: Result<PathBuf, String>,
+    // everything that still parsed, None when the blob isn't json at all
+    pub recovered: Option<Box<State>>,
+    // what recovery had to leave out
+    pub dropped: Vec<String>
+}
+
+// a missing blob is a first start, anything unreadable starts with the default state
+// and keeps the error in the state so it can be shown
+pub fn load_state(raw: Option<String>) -> State {
+    let raw = match raw {
+        Some(raw) => raw,
+        None => return State::default()
+    };
+    parse_state(&raw).unwrap_or_else(|error| {
+        log::error!("can't load saved state: {}", error.message);
+        let mut state = State::default();
+        state.load_error = Some(error);
+        state
+    })
+}
+
+fn parse_state(raw: &str) -> Result<State, LoadError> {
+    read_state(raw).map_err(|mut error| {
+        error.backup = backup(raw).map_err(|e| e.to_string());
+        error
+    })
+}
+
+// parsing without the backup, it's left unwritten
+fn read_state(raw: &str) -> Result<State, LoadError> {
+    let failed = |message: String, recovered: Option<State>, dropped: Vec<String>| LoadError {
+        message,
+        backup: Err("not written".to_owned()),
+        recovered: recovered.map(Box::new),
+        dropped
+    };
+    let mut object = match serde_json::from_str::<Value>(raw) {
+        Ok(Value::Object(object)) => object,
+        Ok(_) => return Err(failed("saved state is not a json object".to_owned(), None, vec![])),
+        Err(error) => return Err(failed(format!("saved state is not valid json: {}", error), None, vec![]))
+    };
+
+    let version = object.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut object);
+        object.insert("version".to_owned(), Value::from(from as u64 + 1));
+    }
+
+    let value = Value::Object(object);
+    let message = match serde_json::from_value::<State>(value.clone()) {
+        Ok(state) if version <= SCHEMA_VERSION => return Ok(state),
+        Ok(_) => format!("saved by a newer version of the app, schema {} while this one reads {}", version, SCHEMA_VERSION),
+        Err(error) => error.to_string()
+    };
+    let (recovered, dropped) = recover(&value);
+    Err(failed(message, Some(recovered), dropped))
+}
+
+// takes every top level field that parses on its own, chords and songs one by one
+fn recover(value: &Value) -> (State, Vec<String>) {
+    let mut dropped = vec![];
+    let mut recovered = serde_json::to_value(State::default()).unwrap();
+    recovered["chords"] = Value::Array(vec![]);
+    recovered["songs"] = Value::Array(vec![]);
+
+    for (key, field) in value.as_object().into_iter().flatten() {
+        let field = match key.as_str() {
+            "chords" => parsable_items::<Chord>(field, "chords", &mut dropped),
+            "songs" => parsable_items::<Song>(field, "songs", &mut dropped),
+            _ => field.clone()
+        };
+        let mut attempt = recovered.clone();
+        attempt[key] = field;
+        if serde_json::from_value::<State>(attempt.clone()).is_ok() {
+            recovered = attempt;
+        } else {
+            dropped.push(key.clone());
+        }
+    }
+    recovered["version"] = Value::from(SCHEMA_VERSION);
+    (serde_json::from_value(recovered).unwrap(), dropped)
+}
+
+fn parsable_items<T: DeserializeOwned>(field: &Value, name: &str, dropped: &mut Vec<String>) -> Value {
+    match field {
+        Value::Array(items) => {
+            let parsed = items.iter()
+                .filter(|item| serde_json::from_value::<T>((*item).clone()).is_ok())
+                .cloned()
+                .collect::<Vec<_>>();
+            if parsed.len() < items.len() {
+                dropped.push(format!("{} of {} {}", items.len() - parsed.len(), items.len(), name));
+            }
+            Value::Array(parsed)
+        }
+        _ => field.clone()
+    }
+}
+
+// next to the app storage, named by the time of the failed load so backups never overwrite each other
+fn backup(raw: &str) -> io::Result<PathBuf> {
+    let dir = directories_next::ProjectDirs::from("", "", APP_NAME)
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."));
+    fs::create_dir_all(&dir)?;
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let mut file = dir.join(format!("state-backup-{}.json", seconds));
+    let mut copy = 1;
+    while file.exists() {
+        copy += 1;
+        file = dir.join(format!("state-backup-{}-{}.json", seconds, copy));
+    }
+    fs::write(&file, raw)?;
+    Ok(file)
+}
+
+// blobs from before versioning: every field added since has a default, only the version is new
+fn migrate_unversioned(_state: &mut Map<String, Value>) {}
+
+// chord ids were counted up from the last chord and could repeat, songs had none and were
+// selected and linked by name. A preference goes to the first chord with its name and old id.
+fn migrate_ids(state: &mut Map<String, Value>) {
+    let mut chord_ids = HashMap::new();
+    if let Some(Value::Array(chords)) = state.get_mut("chords") {
+        for chord in chords.iter_mut().filter_map(|c| c.as_object_mut()) {
+            let id = new_id();
+            if let (Some(Value::String(name)), Some(old)) = (chord.get("name"), chord.get("id")) {
+                chord_ids.entry((name.clone(), old.to_string())).or_insert(id);
+            }
+            chord.insert("id".to_owned(), Value::from(id));
+        }
+    }
+
+    let selected_name = state.get("selected_song").cloned();
+    let mut selected_id = Value::Null;
+    if let Some(Value::Array(songs)) = state.get_mut("songs") {
+        for song in songs.iter_mut().filter_map(|s| s.as_object_mut()) {
+            let id = new_id();
+            song.insert("id".to_owned(), Value::from(id));
+            if selected_id.is_null() && song.get("name") == selected_name.as_ref() {
+                selected_id = Value::from(id);
+            }
+            if let Some(Value::Object(preferences)) = song.get_mut("preferences") {
+                *preferences = preferences.iter()
+                    .filter_map(|(name, old)| {
+                        let id = chord_ids.get(&(name.clone(), old.to_string()))?;
+                        Some((name.clone(), Value::from(*id)))
+                    })
+                    .collect();
+            }
+        }
+    }
+    state.insert("selected_song".to_owned(), selected_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use super::*;
+
+    // the default state as saved by this version, edited by the test
+    fn saved() -> Value {
+        serde_json::to_value(State::default()).unwrap()
+    }
+
+    #[test]
+    fn blobs_from_before_versioning_load() {
+        let raw = r#"{
+            "chords": [{"id": 0, "name": "C", "notes": [[3, 5], [2, 4]]}],
+            "songs": [{"name": "One", "text": "C\nla", "preferences": {}}],
+            "selected_tab": "Songs",
+            "selected_chord": "C",
+            "chord_search_input": "",
+            "selected_song": "",
+            "song_search_input": ""
+        }"#;
+        let state = read_state(raw).ok().unwrap();
+        assert_eq!(state.version, SCHEMA_VERSION);
+        assert_eq!(state.chords[0].notes, vec![(3, 5), (2, 4)]);
+        assert_eq!(state.songs[0].text, "C\nla");
+        assert!(state.selected_tab == crate::state::Tab::Songs);
+    }
+
+    #[test]
+    fn blobs_from_a_newer_version_are_offered_for_recovery() {
+        let mut value = saved();
+        value["version"] = Value::from(SCHEMA_VERSION + 1);
+        value["songs"][0]["name"] = Value::from("Kept");
+        let error = read_state(&value.to_string()).err().unwrap();
+        assert!(error.message.contains("newer version"));
+        assert_eq!(error.recovered.unwrap().songs[0].name, "Kept");
+        assert!(error.dropped.is_empty());
+    }
+
+    #[test]
+    fn broken_fields_are_dropped_one_by_one() {
+        let mut value = saved();
+        value["songs"].as_array_mut().unwrap().push(serde_json::json!({"name": "Broken", "text": 5}));
+        value["selected_tab"] = Value::from("Nowhere");
+        let error = read_state(&value.to_string()).err().unwrap();
+        assert_eq!(error.dropped, vec!["selected_tab".to_owned(), "1 of 2 songs".to_owned()]);
+        let recovered = error.recovered.unwrap();
+        assert_eq!(recovered.songs.len(), 1);
+        assert_eq!(recovered.chords.len(), 1);
+        assert!(recovered.selected_tab == crate::state::Tab::Chords);
+
+        assert!(read_state("[1, 2]").err().unwrap().recovered.is_none());
+        assert!(read_state("{").err().unwrap().recovered.is_none());
+    }
+
+    #[test]
+    fn ids_from_before_they_were_unique_are_migrated() {
+        let raw = r#"{
+            "chords": [
+                {"id": 0, "name": "C", "notes": [[3, 5]]},
+                {"id": 0, "name": "G", "notes": [[3, 6]]},
+                {"id": 1, "name": "C", "notes": [[8, 6]]}
+            ],
+            "songs": [
+                {"name": "One", "text": "C G", "preferences": {"C": 1, "G": 0, "D": 7}},
+                {"name": "Two", "text": "", "preferences": {}}
+            ],
+            "selected_tab": "Songs",
+            "selected_chord": "",
+            "chord_search_input": "",
+            "selected_song": "Two",
+            "song_search_input": ""
+        }"#;
+        let state = read_state(raw).ok().unwrap();
+        let chord_ids = state.chords.iter().map(|chord| chord.id).collect::<Vec<_>>();
+        assert!(chord_ids.iter().all_unique());
+        assert_ne!(state.songs[0].id, state.songs[1].id);
+        assert_eq!(state.selected_song, Some(state.songs[1].id));
+        // preferences follow the chord with their name and old id, unknown ones are dropped
+        assert_eq!(state.songs[0].preferences.len(), 2);
+        assert_eq!(state.songs[0].preferences["C"], chord_ids[2]);
+        assert_eq!(state.songs[0].preferences["G"], chord_ids[1]);
+    }
+}
@@ -0,0 +1,414 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+pub const SEMITONES: i32 = 12;
+
+// natural note names, ordered the way they appear on the staff starting from C
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Letter {
+    C, D, E, F, G, A, B
+}
+
+impl Letter {
+    pub fn all() -> [Letter; 7] {
+        use Letter::*;
+        [C, D, E, F, G, A, B]
+    }
+
+    pub fn semitone(&self) -> i32 {
+        match self {
+            Letter::C => 0,
+            Letter::D => 2,
+            Letter::E => 4,
+            Letter::F => 5,
+            Letter::G => 7,
+            Letter::A => 9,
+            Letter::B => 11
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Letter> {
+        match c.to_ascii_uppercase() {
+            'C' => Some(Letter::C),
+            'D' => Some(Letter::D),
+            'E' => Some(Letter::E),
+            'F' => Some(Letter::F),
+            'G' => Some(Letter::G),
+            'A' => Some(Letter::A),
+            'B' => Some(Letter::B),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Letter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// one of the twelve semitones of the octave, C = 0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PitchClass(u8);
+
+impl PitchClass {
+    pub fn new(semitone: i32) -> Self {
+        PitchClass(semitone.rem_euclid(SEMITONES) as u8)
+    }
+
+    pub fn all() -> impl Iterator<Item = PitchClass> {
+        (0..SEMITONES).map(PitchClass::new)
+    }
+
+    pub fn semitone(&self) -> i32 {
+        self.0 as i32
+    }
+
+    pub fn transpose(&self, semitones: i32) -> PitchClass {
+        PitchClass::new(self.semitone() + semitones)
+    }
+
+    // upward interval from self to other, always within one octave
+    pub fn interval_to(&self, other: PitchClass) -> Interval {
+        Interval((other.semitone() - self.semitone()).rem_euclid(SEMITONES))
+    }
+
+    // default spelling: naturals stay natural, black keys get a sharp or a flat
+    pub fn spell(&self, prefer_flats: bool) -> Note {
+        let natural = Letter::all().into_iter().find(|l| l.semitone() == self.semitone());
+        match natural {
+            Some(letter) => Note::natural(letter),
+            None if prefer_flats => Note::new(Letter::all().into_iter().find(|l| l.semitone() == self.semitone() + 1).unwrap(), -1),
+            None => Note::new(Letter::all().into_iter().find(|l| l.semitone() == self.semitone() - 1).unwrap(), 1)
+        }
+    }
+}
+
+impl fmt::Display for PitchClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.spell(false))
+    }
+}
+
+// spelled note name: a letter plus accidentals, so C# and Db are different notes
+// that share the same pitch class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Note {
+    pub letter: Letter,
+    // positive for sharps, negative for flats
+    pub accidental: i8
+}
+
+impl Note {
+    pub fn new(letter: Letter, accidental: i8) -> Self {
+        Note { letter, accidental }
+    }
+
+    pub fn natural(letter: Letter) -> Self {
+        Note::new(letter, 0)
+    }
+
+    pub fn pitch_class(&self) -> PitchClass {
+        PitchClass::new(self.letter.semitone() + self.accidental as i32)
+    }
+
+    // other spelling of the same pitch class, e.g. C# <-> Db; naturals stay as they are
+    pub fn enharmonic(&self) -> Note {
+        match self.accidental {
+            0 => *self,
+            a if a > 0 => self.pitch_class().spell(true),
+            _ => self.pitch_class().spell(false)
+        }
+    }
+
+    // transposes by semitones keeping the simplest spelling in the requested direction,
+    // whole octaves keep the note as it was written
+    pub fn transpose(&self, semitones: i32, prefer_flats: bool) -> Note {
+        if semitones.rem_euclid(SEMITONES) == 0 {
+            return *self;
+        }
+        self.pitch_class().transpose(semitones).spell(prefer_flats)
+    }
+
+    // parses a note name from the start of the string, returning the note and how many bytes were consumed
+    pub fn parse_prefix(s: &str) -> Option<(Note, usize)> {
+        let mut chars = s.char_indices();
+        let (_, first) = chars.next()?;
+        if !first.is_ascii_uppercase() {
+            return None;
+        }
+        let letter = Letter::from_char(first)?;
+        let mut accidental: i8 = 0;
+        let mut consumed = first.len_utf8();
+        for (idx, c) in chars {
+            match c {
+                '#' | '♯' => accidental += 1,
+                'b' | '♭' => accidental -= 1,
+                _ => break
+            }
+            consumed = idx + c.len_utf8();
+        }
+        Some((Note::new(letter, accidental), consumed))
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let accidentals = if self.accidental >= 0 {
+            "#".repeat(self.accidental as usize)
+        } else {
+            "b".repeat(self.accidental.unsigned_abs() as usize)
+        };
+        write!(f, "{}{}", self.letter, accidentals)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsePitchError(pub String);
+
+impl fmt::Display for ParsePitchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "can't parse \"{}\" as a note", self.0)
+    }
+}
+
+impl FromStr for Note {
+    type Err = ParsePitchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Note::parse_prefix(s) {
+            Some((note, consumed)) if consumed == s.len() => Ok(note),
+            _ => Err(ParsePitchError(s.to_owned()))
+        }
+    }
+}
+
+// note with an octave number, scientific pitch notation: C4 is middle C, A4 is 440Hz
+// stored as its name ("E2") so saved tunings stay readable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Pitch {
+    pub note: Note,
+    pub octave: i32
+}
+
+impl Pitch {
+    pub fn new(note: Note, octave: i32) -> Self {
+        Pitch { note, octave }
+    }
+
+    pub fn from_midi(midi: i32, prefer_flats: bool) -> Self {
+        let note = PitchClass::new(midi).spell(prefer_flats);
+        Pitch::new(note, midi.div_euclid(SEMITONES) - 1)
+    }
+
+    // octave number follows the letter, so B#3 has the same midi number as C4
+    pub fn midi(&self) -> i32 {
+        (self.octave + 1) * SEMITONES + self.note.letter.semitone() + self.note.accidental as i32
+    }
+
+    pub fn pitch_class(&self) -> PitchClass {
+        self.note.pitch_class()
+    }
+
+    pub fn transpose(&self, semitones: i32, prefer_flats: bool) -> Pitch {
+        Pitch::from_midi(self.midi() + semitones, prefer_flats)
+    }
+
+    pub fn frequency(&self) -> f32 {
+        440.0 * 2f32.powf((self.midi() - 69) as f32 / SEMITONES as f32)
+    }
+}
+
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.note, self.octave)
+    }
+}
+
+impl FromStr for Pitch {
+    type Err = ParsePitchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (note, consumed) = Note::parse_prefix(s).ok_or_else(|| ParsePitchError(s.to_owned()))?;
+        let octave = s[consumed..].parse::<i32>().map_err(|_| ParsePitchError(s.to_owned()))?;
+        Ok(Pitch::new(note, octave))
+    }
+}
+
+impl From<Pitch> for String {
+    fn from(pitch: Pitch) -> String {
+        pitch.to_string()
+    }
+}
+
+impl TryFrom<String> for Pitch {
+    type Error = ParsePitchError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// distance in semitones, may be negative or span several octaves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Interval(pub i32);
+
+impl Interval {
+    pub fn semitones(&self) -> i32 {
+        self.0
+    }
+
+    // interval folded into a single octave
+    pub fn simple(&self) -> Interval {
+        Interval(self.0.rem_euclid(SEMITONES))
+    }
+
+    pub fn short_name(&self) -> &'static str {
+        match self.simple().0 {
+            0 => "1",
+            1 => "b2",
+            2 => "2",
+            3 => "b3",
+            4 => "3",
+            5 => "4",
+            6 => "b5",
+            7 => "5",
+            8 => "b6",
+            9 => "6",
+            10 => "b7",
+            _ => "7"
+        }
+    }
+}
+
+// note, chord and key names start with a capital, typed in search "f#" is read as F#
+pub fn capitalized(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new()
+    }
+}
+
+// tonal center used to pick between sharps and flats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Key {
+    pub tonic: PitchClass,
+    pub minor: bool
+}
+
+impl Key {
+    pub fn new(tonic: PitchClass, minor: bool) -> Self {
+        Key { tonic, minor }
+    }
+
+    // F, Bb, Eb, Ab, Db major and D, G, C, F, Bb, Eb minor are written with flats
+    pub fn prefers_flats(&self) -> bool {
+        if self.minor && self.tonic.semitone() == 3 {
+            return true;
+        }
+        let relative_major = if self.minor { self.tonic.transpose(3) } else { self.tonic };
+        matches!(relative_major.semitone(), 5 | 10 | 3 | 8 | 1)
+    }
+
+    pub fn transpose(&self, semitones: i32) -> Key {
+        Key::new(self.tonic.transpose(semitones), self.minor)
+    }
+
+    pub fn tonic_note(&self) -> Note {
+        self.tonic.spell(self.prefers_flats())
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.tonic_note(), if self.minor { "m" } else { "" })
+    }
+}
+
+impl FromStr for Key {
+    type Err = ParsePitchError;
+
+    // "G", "Bb", "F#m", "C minor"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let (note, consumed) = Note::parse_prefix(trimmed).ok_or_else(|| ParsePitchError(s.to_owned()))?;
+        let minor = match trimmed[consumed..].trim() {
+            "" | "maj" | "major" | "M" => false,
+            "m" | "min" | "minor" => true,
+            _ => return Err(ParsePitchError(s.to_owned()))
+        };
+        Ok(Key::new(note.pitch_class(), minor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(text: &str) -> Note {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_prefix() {
+        assert_eq!(Note::parse_prefix("C"), Some((Note::natural(Letter::C), 1)));
+        assert_eq!(Note::parse_prefix("F#m7"), Some((Note::new(Letter::F, 1), 2)));
+        assert_eq!(Note::parse_prefix("Bbsus4"), Some((Note::new(Letter::B, -1), 2)));
+        assert_eq!(Note::parse_prefix("E♭/G"), Some((Note::new(Letter::E, -1), "E♭".len())));
+        assert_eq!(Note::parse_prefix("Ebb"), Some((Note::new(Letter::E, -2), 3)));
+        // lowercase is a lyric word, not a note
+        assert_eq!(Note::parse_prefix("am"), None);
+        assert_eq!(Note::parse_prefix("Hm"), None);
+        assert_eq!(Note::parse_prefix(""), None);
+        assert!("C#x".parse::<Note>().is_err());
+    }
+
+    #[test]
+    fn midi_numbers() {
+        assert_eq!("C4".parse::<Pitch>().unwrap().midi(), 60);
+        assert_eq!("A4".parse::<Pitch>().unwrap().midi(), 69);
+        assert_eq!("E2".parse::<Pitch>().unwrap().midi(), 40);
+        // the octave follows the letter
+        assert_eq!("B#3".parse::<Pitch>().unwrap().midi(), 60);
+        assert_eq!("Cb4".parse::<Pitch>().unwrap().midi(), 59);
+        for midi in 0..128 {
+            assert_eq!(Pitch::from_midi(midi, false).midi(), midi);
+            assert_eq!(Pitch::from_midi(midi, true).midi(), midi);
+        }
+        assert_eq!(Pitch::from_midi(61, true).to_string(), "Db4");
+        assert_eq!(Pitch::from_midi(61, false).to_string(), "C#4");
+        assert!(("A4".parse::<Pitch>().unwrap().frequency() - 440.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn enharmonic_spelling() {
+        assert_eq!(note("C#").enharmonic(), note("Db"));
+        assert_eq!(note("Gb").enharmonic(), note("F#"));
+        assert_eq!(note("E").enharmonic(), note("E"));
+        assert_eq!(note("B#").enharmonic(), note("C"));
+        assert_eq!(note("Fb").enharmonic(), note("E"));
+        assert_eq!(PitchClass::new(10).spell(true), note("Bb"));
+        assert_eq!(PitchClass::new(10).spell(false), note("A#"));
+        assert_eq!(PitchClass::new(-1), PitchClass::new(11));
+        assert_eq!(note("A").transpose(1, true), note("Bb"));
+        assert_eq!(note("A").transpose(1, false), note("A#"));
+        assert_eq!(PitchClass::new(9).interval_to(PitchClass::new(0)).short_name(), "b3");
+    }
+
+    #[test]
+    fn keys_prefer_flats() {
+        let key = |text: &str| text.parse::<Key>().unwrap();
+        for flat in ["F", "Bb", "Eb", "Ab", "Db", "Dm", "Gm", "Cm", "Fm", "Bbm", "Ebm"] {
+            assert!(key(flat).prefers_flats(), "{}", flat);
+        }
+        for sharp in ["C", "G", "D", "A", "E", "B", "F#", "Am", "Em", "Bm", "F#m", "C#m"] {
+            assert!(!key(sharp).prefers_flats(), "{}", sharp);
+        }
+        assert_eq!(key("C minor"), key("Cm"));
+        assert_eq!(key("A#").to_string(), "Bb");
+        assert!("Cx".parse::<Key>().is_err());
+    }
+}
@@ -0,0 +1,64 @@
+use serde::{Serialize, Deserialize};
+use crate::pitch::{Key, PitchClass};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scale {
+    #[default]
+    Major,
+    NaturalMinor,
+    HarmonicMinor,
+    MajorPentatonic,
+    MinorPentatonic,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian
+}
+
+impl Scale {
+    pub fn all() -> [Scale; 10] {
+        use Scale::*;
+        [Major, NaturalMinor, HarmonicMinor, MajorPentatonic, MinorPentatonic, Dorian, Phrygian, Lydian, Mixolydian, Locrian]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scale::Major => "major (ionian)",
+            Scale::NaturalMinor => "minor (aeolian)",
+            Scale::HarmonicMinor => "harmonic minor",
+            Scale::MajorPentatonic => "major pentatonic",
+            Scale::MinorPentatonic => "minor pentatonic",
+            Scale::Dorian => "dorian",
+            Scale::Phrygian => "phrygian",
+            Scale::Lydian => "lydian",
+            Scale::Mixolydian => "mixolydian",
+            Scale::Locrian => "locrian"
+        }
+    }
+
+    // semitones above the root
+    pub fn intervals(&self) -> &'static [i32] {
+        match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::NaturalMinor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::Locrian => &[0, 1, 3, 5, 6, 8, 10]
+        }
+    }
+
+    // scales with a minor third spell their notes like a minor key
+    pub fn key(&self, root: PitchClass) -> Key {
+        Key::new(root, self.intervals().contains(&3))
+    }
+
+    pub fn contains(&self, root: PitchClass, tone: PitchClass) -> bool {
+        self.intervals().contains(&root.interval_to(tone).semitones())
+    }
+}
@@ -0,0 +1,387 @@
+// Structured view of a song text: sections made of lines, chords anchored to columns of
+// the lyric line under them. The text in the editor stays the source of truth, a sheet is
+// parsed from it and written back only when an operation like transposition changes chords.
+
+use itertools::Itertools;
+use crate::pitch::Key;
+use crate::symbol::{ChordSymbol, Quality};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SectionKind {
+    // lines before the first header
+    Untitled,
+    Intro,
+    Verse,
+    PreChorus,
+    Chorus,
+    Bridge,
+    Solo,
+    Outro,
+    Other(String)
+}
+
+impl SectionKind {
+    fn from_label(label: &str) -> SectionKind {
+        match label.to_lowercase().as_str() {
+            "intro" => SectionKind::Intro,
+            "verse" => SectionKind::Verse,
+            "pre-chorus" | "prechorus" | "pre chorus" => SectionKind::PreChorus,
+            "chorus" | "refrain" => SectionKind::Chorus,
+            "bridge" => SectionKind::Bridge,
+            "solo" | "instrumental" | "interlude" => SectionKind::Solo,
+            "outro" | "ending" => SectionKind::Outro,
+            _ => SectionKind::Other(label.to_owned())
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            SectionKind::Untitled => "".to_owned(),
+            SectionKind::Intro => "Intro".to_owned(),
+            SectionKind::Verse => "Verse".to_owned(),
+            SectionKind::PreChorus => "Pre-chorus".to_owned(),
+            SectionKind::Chorus => "Chorus".to_owned(),
+            SectionKind::Bridge => "Bridge".to_owned(),
+            SectionKind::Solo => "Solo".to_owned(),
+            SectionKind::Outro => "Outro".to_owned(),
+            SectionKind::Other(label) => label.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChordEvent {
+    // column in chars of the lyric line the chord is played over
+    pub offset: usize,
+    pub symbol: ChordSymbol
+}
+
+// bar lines, repeat signs and N.C. written on a chord line, kept at their column
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mark {
+    pub offset: usize,
+    pub text: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    // a chord line, a lyric line or both; chord-only lines have no lyric
+    Lyric {
+        chords: Vec<ChordEvent>,
+        marks: Vec<Mark>,
+        lyric: Option<String>,
+        // index of the first text line this one was parsed from
+        text_line: usize
+    },
+    Blank,
+    // ChordPro directives and anything else kept as it was written
+    Directive(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub kind: SectionKind,
+    // header line exactly as written, e.g. "[Chorus]", "Verse 2:" or "{start_of_chorus}"
+    pub header: Option<String>,
+    pub lines: Vec<Line>
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SongSheet {
+    pub sections: Vec<Section>
+}
+
+// a line counts as a chord line when every word on it is a chord symbol or a mark,
+// this keeps lyrics like "A little while" from being treated as chords
+pub fn is_chord_line(line: &str) -> bool {
+    let mut words = line.split_whitespace().peekable();
+    words.peek().is_some() && words.all(|w| ChordSymbol::parse(w).is_ok() || is_mark(w))
+}
+
+// "|", "||", "|:", ":|", "/" and "%" between chords, repeat counts like "x2" or "(2x)" and no chord
+pub fn is_mark(word: &str) -> bool {
+    let bare = word.trim_start_matches('(').trim_end_matches(')');
+    let is_repeat = bare.strip_prefix(['x', 'X'])
+        .or_else(|| bare.strip_suffix(['x', 'X']))
+        .is_some_and(|count| !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()));
+    let is_bar = word.contains('|') && word.chars().all(|c| c == '|' || c == ':');
+    is_repeat || is_bar || matches!(word, "/" | "%") || matches!(bare, "N.C." | "N.C" | "NC")
+}
+
+// words of a line with their starting column in chars
+fn words_with_columns(line: &str) -> Vec<(usize, String)> {
+    let mut words = vec![];
+    let mut word_start = None;
+    for (column, c) in line.chars().chain(std::iter::once(' ')).enumerate() {
+        match (c.is_whitespace(), word_start) {
+            (false, None) => word_start = Some(column),
+            (true, Some(start)) => {
+                words.push((start, line.chars().skip(start).take(column - start).collect()));
+                word_start = None;
+            }
+            _ => ()
+        }
+    }
+    words
+}
+
+// chords of a chord line together with their starting column in chars
+pub fn chord_line_symbols(line: &str) -> Vec<ChordEvent> {
+    words_with_columns(line).into_iter()
+        .filter_map(|(offset, word)| ChordSymbol::parse(&word).ok().map(|symbol| ChordEvent { offset, symbol }))
+        .collect()
+}
+
+pub fn chord_line_marks(line: &str) -> Vec<Mark> {
+    words_with_columns(line).into_iter()
+        .filter(|(_, word)| ChordSymbol::parse(word).is_err() && is_mark(word))
+        .map(|(offset, text)| Mark { offset, text })
+        .collect()
+}
+
+// chords and marks of a chord line as written, in column order
+pub fn chord_line_words(chords: &[ChordEvent], marks: &[Mark]) -> Vec<(usize, String)> {
+    chords.iter().map(|event| (event.offset, event.symbol.to_string()))
+        .chain(marks.iter().map(|mark| (mark.offset, mark.text.clone())))
+        .sorted_by_key(|(offset, _)| *offset)
+        .collect()
+}
+
+// writes chords and marks back at their columns, if a longer chord runs into the next word
+// they stay separated by one space
+pub fn render_chord_line(chords: &[ChordEvent], marks: &[Mark]) -> String {
+    let mut result = String::new();
+    let mut length = 0;
+    for (offset, name) in chord_line_words(chords, marks) {
+        let target = if length == 0 { offset } else { offset.max(length + 1) };
+        result.push_str(&" ".repeat(target - length));
+        length = target + name.chars().count();
+        result.push_str(&name);
+    }
+    result
+}
+
+// "Verse 2:", "[Chorus]", "Bridge" and the ChordPro {start_of_...} directives
+fn section_header(line: &str) -> Option<SectionKind> {
+    let trimmed = line.trim();
+    if let Some(directive) = trimmed.strip_prefix('{').and_then(|d| d.strip_suffix('}')) {
+        let name = directive.split(':').next().unwrap_or("").trim();
+        return match name {
+            "soc" => Some(SectionKind::Chorus),
+            "sov" => Some(SectionKind::Verse),
+            "sob" => Some(SectionKind::Bridge),
+            _ => name.strip_prefix("start_of_").map(SectionKind::from_label)
+        };
+    }
+
+    let label = trimmed.trim_end_matches(':');
+    let label = label.strip_prefix('[').and_then(|l| l.strip_suffix(']')).unwrap_or(label);
+    let label = label.trim_end_matches(|c: char| c.is_ascii_digit() || c.is_whitespace());
+    match SectionKind::from_label(label) {
+        SectionKind::Other(_) => None,
+        kind => Some(kind)
+    }
+}
+
+fn is_section_end(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.starts_with("{end_of_") || matches!(trimmed, "{eoc}" | "{eov}" | "{eob}")
+}
+
+impl SongSheet {
+    pub fn parse(text: &str) -> SongSheet {
+        let text_lines = text.split('\n').collect::<Vec<_>>();
+        let mut sections = vec![Section { kind: SectionKind::Untitled, header: None, lines: vec![] }];
+        let mut idx = 0;
+        while idx < text_lines.len() {
+            let line = text_lines[idx];
+            let section = sections.last_mut().unwrap();
+            if is_chord_line(line) {
+                let lyric = text_lines.get(idx + 1)
+                    .filter(|next| is_lyric_line(next))
+                    .map(|next| next.to_string());
+                section.lines.push(Line::Lyric {
+                    chords: chord_line_symbols(line),
+                    marks: chord_line_marks(line),
+                    text_line: idx,
+                    lyric: lyric.clone()
+                });
+                idx += if lyric.is_some() { 2 } else { 1 };
+                continue;
+            }
+
+            if let Some(kind) = section_header(line) {
+                sections.push(Section { kind, header: Some(line.to_owned()), lines: vec![] });
+            } else if is_section_end(line) {
+                section.lines.push(Line::Directive(line.to_owned()));
+                sections.push(Section { kind: SectionKind::Untitled, header: None, lines: vec![] });
+            } else if line.trim().is_empty() {
+                section.lines.push(Line::Blank);
+            } else if is_directive(line) {
+                section.lines.push(Line::Directive(line.to_owned()));
+            } else {
+                section.lines.push(Line::Lyric { chords: vec![], marks: vec![], lyric: Some(line.to_owned()), text_line: idx });
+            }
+            idx += 1;
+        }
+
+        // drop the empty leading section when the text starts with a header
+        sections.retain(|s| s.header.is_some() || !s.lines.is_empty());
+        SongSheet { sections }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text_lines: Vec<String> = vec![];
+        for section in self.sections.iter() {
+            if let Some(header) = &section.header {
+                text_lines.push(header.clone());
+            }
+            for line in section.lines.iter() {
+                match line {
+                    Line::Lyric { chords, marks, lyric, .. } => {
+                        if !chords.is_empty() || !marks.is_empty() {
+                            text_lines.push(render_chord_line(chords, marks));
+                        }
+                        if let Some(lyric) = lyric {
+                            text_lines.push(lyric.clone());
+                        }
+                    }
+                    Line::Blank => text_lines.push("".to_owned()),
+                    Line::Directive(directive) => text_lines.push(directive.clone())
+                }
+            }
+        }
+        text_lines.join("\n")
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &Line> {
+        self.sections.iter().flat_map(|s| s.lines.iter())
+    }
+
+    pub fn chord_events(&self) -> impl Iterator<Item = &ChordEvent> {
+        self.lines().flat_map(|line| match line {
+            Line::Lyric { chords, .. } => chords.iter(),
+            _ => [].iter()
+        })
+    }
+
+    // every chord used, in order of first appearance
+    pub fn chords(&self) -> Vec<ChordSymbol> {
+        let mut chords: Vec<ChordSymbol> = vec![];
+        for event in self.chord_events() {
+            if !chords.iter().any(|c| c.to_string() == event.symbol.to_string()) {
+                chords.push(event.symbol.clone());
+            }
+        }
+        chords
+    }
+
+    // chord written at the given text line and column, used to follow the editor cursor
+    pub fn chord_at(&self, text_line: usize, column: usize) -> Option<&ChordEvent> {
+        self.lines().find_map(|line| match line {
+            Line::Lyric { chords, text_line: chord_line, .. } if !chords.is_empty() && *chord_line == text_line => {
+                chords.iter().find(|event| {
+                    let length = event.symbol.to_string().chars().count();
+                    column >= event.offset && column <= event.offset + length
+                })
+            }
+            _ => None
+        })
+    }
+
+    // first chord of the song is taken as the tonic, good enough for pop and folk charts
+    pub fn guess_key(&self) -> Option<Key> {
+        self.chord_events()
+            .map(|event| Key::new(event.symbol.root.pitch_class(), event.symbol.quality == Quality::Minor))
+            .next()
+    }
+
+    pub fn transpose(&mut self, semitones: i32, prefer_flats: bool) {
+        for section in self.sections.iter_mut() {
+            for line in section.lines.iter_mut() {
+                if let Line::Lyric { chords, .. } = line {
+                    for event in chords.iter_mut() {
+                        event.symbol = event.symbol.transpose(semitones, prefer_flats);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn is_lyric_line(line: &str) -> bool {
+    !line.trim().is_empty()
+        && !is_chord_line(line)
+        && section_header(line).is_none()
+        && !is_directive(line)
+}
+
+fn is_directive(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.starts_with('{') && trimmed.ends_with('}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(chords: &[ChordEvent]) -> Vec<(usize, String)> {
+        chords.iter().map(|event| (event.offset, event.symbol.to_string())).collect()
+    }
+
+    #[test]
+    fn sections_start_at_headers() {
+        let text = "Title line\n\nVerse 1:\nfirst\n[Chorus]\nsecond\n{start_of_bridge}\nthird\n{end_of_bridge}\nOutro\n{title: x}";
+        let sheet = SongSheet::parse(text);
+        let kinds = sheet.sections.iter().map(|s| (s.kind.clone(), s.header.clone())).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![
+            (SectionKind::Untitled, None),
+            (SectionKind::Verse, Some("Verse 1:".to_owned())),
+            (SectionKind::Chorus, Some("[Chorus]".to_owned())),
+            (SectionKind::Bridge, Some("{start_of_bridge}".to_owned())),
+            (SectionKind::Outro, Some("Outro".to_owned()))
+        ]);
+        assert_eq!(sheet.sections[0].lines, vec![
+            Line::Lyric { chords: vec![], marks: vec![], lyric: Some("Title line".to_owned()), text_line: 0 },
+            Line::Blank
+        ]);
+        assert_eq!(sheet.sections[3].lines[1], Line::Directive("{end_of_bridge}".to_owned()));
+        assert_eq!(sheet.sections[4].lines, vec![Line::Directive("{title: x}".to_owned())]);
+        // unknown labels are lyrics, not headers
+        assert!(SongSheet::parse("Coda:").sections[0].header.is_none());
+        assert_eq!(sheet.to_text(), text);
+    }
+
+    #[test]
+    fn chords_anchor_to_the_lyric_below() {
+        let text = "G       C\nAmazing grace\nA little while\nD  G\n\nEm";
+        let sheet = SongSheet::parse(text);
+        let lines = sheet.lines().collect::<Vec<_>>();
+        match lines[0] {
+            Line::Lyric { chords, lyric, text_line, .. } => {
+                assert_eq!(names(chords), vec![(0, "G".to_owned()), (8, "C".to_owned())]);
+                assert_eq!(lyric.as_deref(), Some("Amazing grace"));
+                assert_eq!(*text_line, 0);
+            }
+            line => panic!("{:?}", line)
+        }
+        // a lyric starting with a chord-like word stays a lyric
+        assert!(matches!(lines[1], Line::Lyric { chords, .. } if chords.is_empty()));
+        // chord-only lines followed by a blank have no lyric
+        assert!(matches!(lines[2], Line::Lyric { lyric: None, text_line: 3, .. }));
+        assert_eq!(sheet.chord_at(0, 9).map(|event| event.symbol.to_string()), Some("C".to_owned()));
+        assert_eq!(sheet.chord_at(0, 4), None);
+        assert_eq!(sheet.chord_at(1, 0), None);
+        assert_eq!(sheet.chords().iter().map(|c| c.to_string()).collect::<Vec<_>>(), ["G", "C", "D", "Em"]);
+        assert_eq!(sheet.to_text(), text);
+    }
+
+    #[test]
+    fn colliding_chords_stay_apart() {
+        let mut sheet = SongSheet::parse("C D\nla la");
+        sheet.transpose(1, true);
+        assert_eq!(sheet.to_text(), "Db Eb\nla la");
+        sheet.transpose(6, false);
+        assert_eq!(sheet.to_text(), "G A\nla la");
+    }
+}
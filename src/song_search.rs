@@ -0,0 +1,227 @@
+// Searching the songs tab. Words are looked up in an index over the lyrics, titles and artists,
+// every word has to be found in a song and matches from its start, so unfinished words work too.
+// Terms narrow it down:
+//
+//   chord:Bm    songs that play Bm, in any spelling
+//   key:G       songs in G, set by hand or guessed from the chords
+//   artist:x    artist containing x
+//   tag:x       songs tagged x
+//
+// Matching lyric lines are shown as snippets with the words highlighted.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::str::FromStr;
+use crate::models::{Id, Song};
+use crate::pitch::{capitalized, Key};
+use crate::sheet::{Line, SongSheet};
+use crate::symbol::ChordSymbol;
+
+const MAX_SNIPPETS: usize = 3;
+
+// where a word was found, lines are counted in the song text
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Place {
+    Title,
+    Line(usize)
+}
+
+#[derive(Default)]
+pub struct SongIndex {
+    // lowercase word to the songs and places holding it
+    words: BTreeMap<String, BTreeSet<(Id, Place)>>,
+    // lyric lines by song, for the snippets
+    lyrics: BTreeMap<(Id, usize), String>,
+    songs: BTreeMap<Id, IndexedSong>
+}
+
+// what the terms look at, parsed once when the song is indexed
+struct IndexedSong {
+    // the words the song is found under, to take it out again
+    words: BTreeSet<String>,
+    key: Option<Key>,
+    chords: Vec<ChordSymbol>
+}
+
+pub struct Snippet {
+    pub line: usize,
+    pub text: String,
+    // byte ranges of the matched words
+    pub highlights: Vec<Range<usize>>
+}
+
+pub struct SongMatch {
+    pub id: Id,
+    pub snippets: Vec<Snippet>
+}
+
+impl SongIndex {
+    pub fn new(songs: &[Song]) -> SongIndex {
+        let mut index = SongIndex::default();
+        for song in songs {
+            index.add(song);
+        }
+        index
+    }
+
+    // indexes the song again after an edit
+    pub fn update(&mut self, song: &Song) {
+        self.remove(song.id);
+        self.add(song);
+    }
+
+    fn add(&mut self, song: &Song) {
+        let sheet = song.sheet();
+        let mut places = vec![(Place::Title, song.name.clone()), (Place::Title, song.artist.clone())];
+        for (line, lyric) in lyric_lines(&sheet) {
+            places.push((Place::Line(line), lyric.clone()));
+            self.lyrics.insert((song.id, line), lyric);
+        }
+        let mut indexed = IndexedSong { words: BTreeSet::new(), key: song.sheet_key(&sheet), chords: sheet.chords() };
+        for (place, text) in places {
+            for (_, word) in words(&text) {
+                let word = word.to_lowercase();
+                self.words.entry(word.clone()).or_default().insert((song.id, place));
+                indexed.words.insert(word);
+            }
+        }
+        self.songs.insert(song.id, indexed);
+    }
+
+    fn remove(&mut self, id: Id) {
+        let Some(indexed) = self.songs.remove(&id) else {
+            return;
+        };
+        for word in indexed.words {
+            if let Some(places) = self.words.get_mut(&word) {
+                places.retain(|(song, _)| *song != id);
+                if places.is_empty() {
+                    self.words.remove(&word);
+                }
+            }
+        }
+        let lines = self.lyrics.range((id, 0)..=(id, usize::MAX)).map(|(line, _)| *line).collect::<Vec<_>>();
+        for line in lines {
+            self.lyrics.remove(&line);
+        }
+    }
+
+    // places of every indexed word starting with the prefix
+    fn lookup(&self, prefix: &str) -> BTreeSet<(Id, Place)> {
+        self.words.range(prefix.to_owned()..)
+            .take_while(|(word, _)| word.starts_with(prefix))
+            .flat_map(|(_, places)| places.iter().copied())
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct SongQuery {
+    words: Vec<String>,
+    chords: Vec<ChordSymbol>,
+    keys: Vec<Key>,
+    artists: Vec<String>,
+    tags: Vec<String>,
+    // terms that couldn't be read, they are left out of the search
+    pub errors: Vec<String>
+}
+
+impl SongQuery {
+    pub fn parse(input: &str) -> SongQuery {
+        let mut query = SongQuery::default();
+        for token in input.split_whitespace() {
+            let (key, value) = match token.split_once(':') {
+                Some((key, value)) if !value.is_empty() => (key.to_lowercase(), value),
+                _ => {
+                    query.words.extend(words(token).map(|(_, word)| word.to_lowercase()));
+                    continue;
+                }
+            };
+            match key.as_str() {
+                "chord" => match ChordSymbol::parse(&capitalized(value)) {
+                    Ok(symbol) => query.chords.push(symbol),
+                    Err(_) => query.errors.push(format!("can't read chord {}", value))
+                },
+                "key" => match Key::from_str(&capitalized(value)) {
+                    Ok(key) => query.keys.push(key),
+                    Err(_) => query.errors.push(format!("can't read key {}", value))
+                },
+                "artist" => query.artists.push(value.to_lowercase()),
+                "tag" => query.tags.push(value.to_lowercase()),
+                _ => query.errors.push(format!("unknown term {}, use chord, key, artist or tag", key))
+            }
+        }
+        query
+    }
+
+    // songs passing every term, in the order given
+    pub fn run<'a>(&self, index: &SongIndex, songs: impl Iterator<Item = &'a Song>) -> Vec<SongMatch> {
+        let hits = self.words.iter().map(|word| index.lookup(word)).collect::<Vec<_>>();
+        songs
+            .filter(|song| index.songs.get(&song.id).is_some_and(|indexed| self.passes_terms(song, indexed)))
+            .filter(|song| hits.iter().all(|places| places.range((song.id, Place::Title)..=(song.id, Place::Line(usize::MAX))).next().is_some()))
+            .map(|song| {
+                let lines = hits.iter()
+                    .flat_map(|places| places.range((song.id, Place::Title)..=(song.id, Place::Line(usize::MAX))))
+                    .filter_map(|(_, place)| match place {
+                        Place::Line(line) => Some(*line),
+                        Place::Title => None
+                    })
+                    .collect::<BTreeSet<_>>();
+                let snippets = lines.into_iter()
+                    .take(MAX_SNIPPETS)
+                    .map(|line| {
+                        let text = index.lyrics[&(song.id, line)].clone();
+                        let highlights = self.highlights(&text);
+                        Snippet { line, text, highlights }
+                    })
+                    .collect();
+                SongMatch { id: song.id, snippets }
+            })
+            .collect()
+    }
+
+    fn passes_terms(&self, song: &Song, indexed: &IndexedSong) -> bool {
+        let artist = song.artist.to_lowercase();
+        if !self.artists.iter().all(|a| artist.contains(a)) {
+            return false;
+        }
+        if !self.tags.iter().all(|tag| song.tags.iter().any(|t| t.to_lowercase() == *tag)) {
+            return false;
+        }
+        if !self.keys.is_empty() && !indexed.key.is_some_and(|key| self.keys.contains(&key)) {
+            return false;
+        }
+        self.chords.iter().all(|wanted| indexed.chords.iter().any(|chord| chord.is_equivalent(wanted)))
+    }
+
+    fn highlights(&self, text: &str) -> Vec<Range<usize>> {
+        words(text)
+            .filter(|(_, word)| {
+                let word = word.to_lowercase();
+                self.words.iter().any(|w| word.starts_with(w.as_str()))
+            })
+            .map(|(start, word)| start..start + word.len())
+            .collect()
+    }
+}
+
+// words with their byte offsets, apostrophes stay inside: "don't"
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '\''))
+        .filter(|word| !word.is_empty())
+        .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+// lyric lines with their line in the song text, chord lines and directives are left out
+fn lyric_lines(sheet: &SongSheet) -> Vec<(usize, String)> {
+    sheet.lines()
+        .filter_map(|line| match line {
+            Line::Lyric { chords, marks, lyric: Some(lyric), text_line } => {
+                let line = if chords.is_empty() && marks.is_empty() { *text_line } else { text_line + 1 };
+                Some((line, lyric.clone()))
+            }
+            _ => None
+        })
+        .collect()
+}
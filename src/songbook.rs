@@ -0,0 +1,284 @@
+// Printable songbook: every song starts on a new page with its title, a strip of
+// diagrams for the chords it uses and the chords-over-lyrics text in a monospaced font,
+// so chords stay above the syllables they were written over.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::diagram::{Anchor, ChordLayout, DiagramShape, FRET_THICKNESS};
+use crate::format::file_name;
+use crate::models::{Chord, Song};
+use crate::pdf::{Font, Page, PdfDocument, PAGE_HEIGHT, PAGE_WIDTH};
+use crate::sheet::{render_chord_line, Line};
+
+const MARGIN: f32 = 50.0;
+const HEADER_SIZE: f32 = 9.0;
+const TITLE_SIZE: f32 = 20.0;
+const TEXT_SIZE: f32 = 10.0;
+const LINE_HEIGHT: f32 = 12.0;
+const DIAGRAM_SCALE: f32 = 0.5;
+const DIAGRAM_GAP: f32 = 12.0;
+
+struct Writer<'a> {
+    document: PdfDocument,
+    chords: &'a [Chord],
+    song: Option<&'a Song>,
+    // where the free space on the last page starts
+    y: f32
+}
+
+pub fn songbook(songs: &[&Song], chords: &[Chord]) -> PdfDocument {
+    let mut writer = Writer { document: PdfDocument::default(), chords, song: None, y: 0.0 };
+    for song in songs {
+        writer.song = Some(song);
+        writer.new_page();
+        writer.write_song(song);
+    }
+
+    let page_count = writer.document.pages.len();
+    for (idx, page) in writer.document.pages.iter_mut().enumerate() {
+        let label = format!("{} / {}", idx + 1, page_count);
+        let x = (PAGE_WIDTH - Font::Helvetica.text_width(&label, HEADER_SIZE)) / 2.0;
+        page.text(x, PAGE_HEIGHT - MARGIN / 2.0, Font::Helvetica, HEADER_SIZE, &label);
+    }
+    writer.document
+}
+
+// exporting into a directory writes "<song name>.pdf" for one song and "songbook.pdf" for more
+pub fn export_path(songs: &[&Song], chords: &[Chord], path: &Path) -> io::Result<PathBuf> {
+    let file = match songs {
+        [song] if path.is_dir() => path.join(format!("{}.pdf", file_name(&song.name))),
+        _ if path.is_dir() => path.join("songbook.pdf"),
+        _ => path.to_path_buf()
+    };
+    fs::write(&file, songbook(songs, chords).to_bytes())?;
+    Ok(file)
+}
+
+impl<'a> Writer<'a> {
+    fn page(&mut self) -> &mut Page {
+        self.document.pages.last_mut().unwrap()
+    }
+
+    fn new_page(&mut self) {
+        let mut page = Page::default();
+        if let Some(song) = self.song {
+            let header = if song.artist.is_empty() {
+                song.name.clone()
+            } else {
+                format!("{} - {}", song.name, song.artist)
+            };
+            page.text(MARGIN, MARGIN / 2.0 + HEADER_SIZE, Font::Helvetica, HEADER_SIZE, &header);
+            page.line((MARGIN, MARGIN / 2.0 + HEADER_SIZE + 4.0), (PAGE_WIDTH - MARGIN, MARGIN / 2.0 + HEADER_SIZE + 4.0), 0.5);
+        }
+        self.document.pages.push(page);
+        self.y = MARGIN;
+    }
+
+    // moves to a new page when the next block doesn't fit on this one
+    fn reserve(&mut self, height: f32) {
+        if self.y + height > PAGE_HEIGHT - MARGIN {
+            self.new_page();
+        }
+    }
+
+    fn write_song(&mut self, song: &Song) {
+        self.y += TITLE_SIZE;
+        let y = self.y;
+        self.page().text(MARGIN, y, Font::HelveticaBold, TITLE_SIZE, &song.name);
+
+        let mut details = vec![];
+        if !song.artist.is_empty() {
+            details.push(song.artist.clone());
+        }
+        if let Some(key) = song.key() {
+            details.push(format!("key: {}", key));
+        }
+        if song.capo > 0 {
+            details.push(format!("capo: {}", song.capo));
+        }
+        if !details.is_empty() {
+            self.y += LINE_HEIGHT + 2.0;
+            let y = self.y;
+            self.page().text(MARGIN, y, Font::Helvetica, TEXT_SIZE, &details.join("   "));
+        }
+        self.y += LINE_HEIGHT;
+
+        self.write_diagrams(song);
+        self.write_sheet(song);
+    }
+
+    fn write_diagrams(&mut self, song: &Song) {
+        let sheet = song.sheet();
+        let key = song.sheet_key(&sheet);
+        let diagrams = sheet.chords().iter()
+            .filter_map(|symbol| {
+                let sounding = symbol.to_string();
+                let shape_name = song.shape_name_in_key(&sounding, key);
+                let chord = song.preferred_chord(self.chords, &shape_name)?;
+                let label = if shape_name == sounding { sounding } else { format!("{} ({})", sounding, shape_name) };
+                Some((label, chord))
+            })
+            .collect::<Vec<_>>();
+
+        let mut x = MARGIN;
+        let mut row_height: f32 = 0.0;
+        for (label, chord) in diagrams {
+            let layout = ChordLayout::new(&chord.notes, &chord.instrument, song.capo);
+            let width = layout.width * DIAGRAM_SCALE;
+            let height = layout.height * DIAGRAM_SCALE + LINE_HEIGHT;
+            if x + width > PAGE_WIDTH - MARGIN {
+                x = MARGIN;
+                self.y += row_height + DIAGRAM_GAP;
+                row_height = 0.0;
+            }
+            self.reserve(height);
+
+            let y = self.y;
+            let label_x = x + (width - Font::HelveticaBold.text_width(&label, TEXT_SIZE)) / 2.0;
+            let page = self.page();
+            page.text(label_x, y + TEXT_SIZE, Font::HelveticaBold, TEXT_SIZE, &label);
+            draw_diagram(page, &layout, (x, y + LINE_HEIGHT));
+
+            x += width + DIAGRAM_GAP;
+            row_height = row_height.max(height);
+        }
+        self.y += row_height + DIAGRAM_GAP;
+    }
+
+    fn write_sheet(&mut self, song: &Song) {
+        let columns = ((PAGE_WIDTH - 2.0 * MARGIN) / Font::Courier.text_width(" ", TEXT_SIZE)) as usize;
+        for section in song.sheet().sections {
+            if section.header.is_some() {
+                self.reserve(LINE_HEIGHT * 3.0);
+                self.y += LINE_HEIGHT + 4.0;
+                let y = self.y;
+                let title = section_title(section.header.as_deref().unwrap_or(""), &section.kind.name());
+                self.page().text(MARGIN, y, Font::HelveticaBold, TEXT_SIZE, &title);
+            }
+
+            for line in section.lines {
+                match line {
+                    Line::Lyric { chords, marks, lyric, .. } => {
+                        let chord_line = render_chord_line(&chords, &marks);
+                        for (chord_row, lyric_row) in wrap(&chord_line, &lyric.unwrap_or_default(), columns) {
+                            // a chord line is never split from its lyric
+                            let rows = [(Font::CourierBold, chord_row), (Font::Courier, lyric_row)];
+                            let rows = rows.into_iter().filter(|(_, text)| !text.is_empty()).collect::<Vec<_>>();
+                            self.reserve(rows.len() as f32 * LINE_HEIGHT);
+                            for (font, text) in rows {
+                                self.y += LINE_HEIGHT;
+                                let y = self.y;
+                                self.page().text(MARGIN, y, font, TEXT_SIZE, &text);
+                            }
+                        }
+                    }
+                    Line::Blank => self.y += LINE_HEIGHT / 2.0,
+                    Line::Directive(directive) => {
+                        if let Some(comment) = comment(&directive) {
+                            self.reserve(LINE_HEIGHT);
+                            self.y += LINE_HEIGHT;
+                            let y = self.y;
+                            self.page().text(MARGIN, y, Font::HelveticaOblique, TEXT_SIZE, &comment);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// lines wider than the page break at the last space before the margin, the chord line at the same
+// column so chords stay over their syllables; rows with nothing on them are empty
+fn wrap(chord_line: &str, lyric: &str, columns: usize) -> Vec<(String, String)> {
+    let mut chords = chord_line.chars().collect::<Vec<_>>();
+    let mut lyric = lyric.chars().collect::<Vec<_>>();
+    let row = |chars: &[char], end: usize| chars[..end.min(chars.len())].iter().collect::<String>().trim_end().to_owned();
+    let mut rows = vec![];
+    while chords.len().max(lyric.len()) > columns {
+        let length = chords.len().max(lyric.len());
+        let is_blank = |column: usize| [&chords, &lyric].iter().all(|chars| chars.get(column).is_none_or(|c| *c == ' '));
+        // a word longer than the whole row is cut
+        let end = (1..=columns).rev().find(|column| is_blank(column - 1)).unwrap_or(columns);
+        let next = (end..length).find(|column| !is_blank(*column)).unwrap_or(length);
+        rows.push((row(&chords, end), row(&lyric, end)));
+        chords = chords.get(next..).unwrap_or_default().to_vec();
+        lyric = lyric.get(next..).unwrap_or_default().to_vec();
+    }
+    rows.push((row(&chords, columns), row(&lyric, columns)));
+    rows
+}
+
+// "[Verse 2]" and "Verse 2:" print as "Verse 2", ChordPro sections use their label if they have one
+fn section_title(header: &str, kind_name: &str) -> String {
+    let trimmed = header.trim();
+    if let Some(directive) = trimmed.strip_prefix('{').and_then(|d| d.strip_suffix('}')) {
+        return match directive.split_once(':') {
+            Some((_, label)) if !label.trim().is_empty() => label.trim().to_owned(),
+            _ => kind_name.to_owned()
+        };
+    }
+    let label = trimmed.trim_end_matches(':');
+    label.strip_prefix('[').and_then(|l| l.strip_suffix(']')).unwrap_or(label).to_owned()
+}
+
+// {comment: ...} and its short forms are printed, other directives only matter to the editor
+fn comment(directive: &str) -> Option<String> {
+    let inner = directive.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let (name, value) = inner.split_once(':')?;
+    matches!(name.trim(), "comment" | "c" | "comment_italic" | "ci" | "comment_box" | "cb")
+        .then(|| value.trim().to_owned())
+}
+
+fn draw_diagram(page: &mut Page, layout: &ChordLayout, origin: (f32, f32)) {
+    let point = |(x, y): (f32, f32)| (origin.0 + x * DIAGRAM_SCALE, origin.1 + y * DIAGRAM_SCALE);
+    for shape in layout.shapes.iter() {
+        match shape {
+            DiagramShape::Line { from, to, thickness } => page.line(point(*from), point(*to), thickness * DIAGRAM_SCALE),
+            DiagramShape::Circle { center, radius } => page.circle(point(*center), radius * DIAGRAM_SCALE, FRET_THICKNESS * DIAGRAM_SCALE),
+            DiagramShape::Text { pos, text, size, anchor } => {
+                let size = size * DIAGRAM_SCALE;
+                let (x, y) = point(*pos);
+                let x = match anchor {
+                    Anchor::Center => x - Font::Helvetica.text_width(text, size) / 2.0,
+                    Anchor::Left => x
+                };
+                // the layout centers text vertically on the point, pdf places the baseline
+                page.text(x, y + size * 0.35, Font::Helvetica, size, text);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_lines_wrap_with_their_chords() {
+        let rows = wrap("G       C", "Amazing grace how sweet", 12);
+        assert_eq!(rows, vec![
+            ("G".to_owned(), "Amazing".to_owned()),
+            ("C".to_owned(), "grace how".to_owned()),
+            ("".to_owned(), "sweet".to_owned())
+        ]);
+        // a chord name running past a space keeps the break before it
+        let rows = wrap("      Cmaj7", "la la la la la", 8);
+        assert_eq!(rows[0], ("".to_owned(), "la la".to_owned()));
+        assert_eq!(rows[1], ("Cmaj7".to_owned(), "la la la".to_owned()));
+        assert_eq!(wrap("", "abcdefghij", 4).len(), 3);
+    }
+
+    #[test]
+    fn long_songs_continue_on_new_pages() {
+        let mut short = Song::empty("short".to_owned());
+        short.text = "G\nla".to_owned();
+        let mut long = Song::empty("long".to_owned());
+        long.text = vec!["G   C\nla la la"; 60].join("\n");
+        let document = songbook(&[&short, &long], &[]);
+        assert_eq!(document.pages.len(), 1 + 3);
+        let bytes = String::from_utf8(document.to_bytes()).unwrap();
+        assert_eq!(bytes.matches("/Type /Page ").count(), 4);
+        assert!(bytes.contains("/Count 4"));
+    }
+}
@@ -1,46 +1,268 @@
-use crate::models::{Chord, Song};
+use std::path::PathBuf;
+use itertools::Itertools;
+use crate::audio::Player;
+use crate::chord::NotePos;
+use crate::chord_search::ShapeSearch;
+use crate::song_search::{SongIndex, SongMatch, SongQuery};
+use crate::history::History;
+use crate::library::Library;
+use crate::midi::MidiSettings;
+use crate::midi_input::MidiInput;
+use crate::neck::NeckView;
+use crate::persistence::{LoadError, SCHEMA_VERSION};
+use crate::pitch::PitchClass;
+use crate::symbol::ChordSymbol;
+use crate::synth::PlayStyle;
+use crate::models::{new_id, song_name_taken, Chord, Id, Song};
+use crate::instrument::Instrument;
+use crate::voicing::VoicingGenerator;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
 pub struct State {
+    // schema of the saved json, see persistence.rs
+    #[serde(default)]
+    pub version: u64,
     pub chords: Vec<Chord>,
     pub songs: Vec<Song>,
+    // deleted chords, newest first, until they are restored or deleted for good
+    #[serde(default)]
+    pub trash: Vec<Chord>,
 
     pub selected_tab: Tab,
     pub selected_chord: String,
     pub chord_search_input: String,
-    pub selected_song: String,
-    pub song_search_input: String
+    // only chords with the tag are listed
+    #[serde(default)]
+    pub chord_tag_filter: Option<String>,
+    #[serde(default)]
+    pub favorites_only: bool,
+    #[serde(default)]
+    pub chord_sort: ChordSort,
+    // chord names, the last selected or picked first
+    #[serde(default)]
+    pub recent_chords: Vec<String>,
+    pub selected_song: Option<Id>,
+    pub song_search_input: String,
+    #[serde(default)]
+    pub show_archived_songs: bool,
+    #[serde(default)]
+    pub voicing_generator: VoicingGenerator,
+    #[serde(default)]
+    pub neck_view: NeckView,
+    #[serde(default)]
+    pub midi_input: MidiInput,
+    // file or directory used for ChordPro import and export
+    #[serde(default)]
+    pub chordpro_path: String,
+    #[serde(skip)]
+    pub chordpro_status: String,
+    #[serde(default)]
+    pub play_style: PlayStyle,
+    // file or directory chords are saved to as wav
+    #[serde(default)]
+    pub wav_path: String,
+    #[serde(skip)]
+    pub sound_status: String,
+    #[serde(skip)]
+    pub player: Player,
+    // file or directory the songbook pdf is written to
+    #[serde(default)]
+    pub pdf_path: String,
+    #[serde(skip)]
+    pub pdf_status: String,
+    #[serde(default)]
+    pub midi_settings: MidiSettings,
+    // directory the chords and songs are kept in, without one they live in the app storage
+    #[serde(default)]
+    pub library_path: Option<PathBuf>,
+    // chords and songs of the app storage, put aside while a library is open and brought back when it's closed
+    #[serde(default)]
+    pub stored_collection: Option<(Vec<Chord>, Vec<Song>)>,
+    #[serde(default)]
+    pub recent_libraries: Vec<PathBuf>,
+    #[serde(skip)]
+    pub library: Option<Library>,
+    #[serde(skip)]
+    pub library_status: String,
+    #[serde(skip)]
+    pub library_window_open: bool,
+    #[serde(skip)]
+    pub library_path_input: String,
+    // set when the saved state couldn't be loaded, saving waits until the user decides
+    #[serde(skip)]
+    pub load_error: Option<LoadError>,
+    #[serde(skip)]
+    pub history: History,
+    #[serde(skip)]
+    pub history_window_open: bool,
+    // chord waiting for the delete to be confirmed
+    #[serde(skip)]
+    pub chord_to_delete: Option<Id>,
+    #[serde(skip)]
+    pub song_to_delete: Option<Id>,
+    #[serde(skip)]
+    pub shape_search: ShapeSearch,
+    // built when the songs tab searches, dropped whenever messages may have changed the songs
+    #[serde(skip)]
+    pub song_index: Option<SongIndex>,
+    // text line the editor moves the cursor to
+    #[serde(skip)]
+    pub song_jump: Option<usize>,
+    // tag typed in a tag menu, only one is open at a time
+    #[serde(skip)]
+    pub new_tag_input: String,
+    // song being renamed and the name typed so far
+    #[serde(skip)]
+    pub song_rename: Option<(Id, String)>,
+    #[serde(skip)]
+    pub trash_window_open: bool
 }
 
 impl State {
     pub(crate) fn default() -> Self {
         State {
+            version: SCHEMA_VERSION,
             chords: vec![
                 Chord {
-                    id: 0,
-                    name: "Dmaj7".to_string(),
                     notes: vec![
                         (5, 5),
                         (7, 4),
                         (6, 3),
                         (7, 2)
                     ],
+                    ..Chord::empty("Dmaj7".to_string(), Instrument::guitar())
                 }
             ],
             songs: vec![
                 Song {
+                    id: new_id(),
                     name: "Test".to_string(),
                     text: "              Dmaj7 \n The test song that starts with a chord".to_string(),
                     preferences: Default::default(),
+                    instrument: Instrument::guitar(),
+                    capo: 0,
+                    artist: "".to_owned(),
+                    key: None,
+                    tags: vec![],
+                    archived: false
                 }
             ],
+            trash: vec![],
 
             selected_tab: Tab::Chords,
             selected_chord: "".to_owned(),
             chord_search_input: "".to_owned(),
-            selected_song: "".to_owned(),
-            song_search_input: "".to_owned()
+            chord_tag_filter: None,
+            favorites_only: false,
+            chord_sort: ChordSort::Name,
+            recent_chords: vec![],
+            selected_song: None,
+            song_search_input: "".to_owned(),
+            show_archived_songs: false,
+            voicing_generator: VoicingGenerator::default(),
+            neck_view: NeckView::default(),
+            midi_input: MidiInput::default(),
+            chordpro_path: "".to_owned(),
+            chordpro_status: "".to_owned(),
+            play_style: PlayStyle::default(),
+            wav_path: "".to_owned(),
+            sound_status: "".to_owned(),
+            player: Player::default(),
+            pdf_path: "".to_owned(),
+            pdf_status: "".to_owned(),
+            midi_settings: MidiSettings::default(),
+            library_path: None,
+            stored_collection: None,
+            recent_libraries: vec![],
+            library: None,
+            library_status: "".to_owned(),
+            library_window_open: false,
+            library_path_input: "".to_owned(),
+            load_error: None,
+            history: History::default(),
+            history_window_open: false,
+            chord_to_delete: None,
+            song_to_delete: None,
+            shape_search: ShapeSearch::default(),
+            song_index: None,
+            song_jump: None,
+            new_tag_input: "".to_owned(),
+            song_rename: None,
+            trash_window_open: false
+        }
+    }
+
+    pub fn chord_passes_filter(&self, chord: &Chord) -> bool {
+        (!self.favorites_only || chord.favorite)
+            && self.chord_tag_filter.as_ref().is_none_or(|tag| chord.tags.contains(tag))
+    }
+
+    // every tag in use, for the filter and for adding existing tags to a chord
+    pub fn chord_tags(&self) -> Vec<&String> {
+        self.chords.iter().flat_map(|chord| chord.tags.iter()).unique().sorted().collect()
+    }
+
+    pub fn sort_chord_names(&self, names: &mut [String]) {
+        names.sort();
+        match self.chord_sort {
+            ChordSort::Name => (),
+            // names that don't parse go last
+            ChordSort::Root => names.sort_by_key(|name| {
+                ChordSymbol::parse(name).map(|symbol| symbol.root.pitch_class().semitone()).unwrap_or(i32::MAX)
+            }),
+            ChordSort::Quality => names.sort_by_key(|name| match ChordSymbol::parse(name) {
+                Ok(symbol) => (0, Some((symbol.quality, symbol.extension, symbol.major_seventh)), symbol.root.pitch_class()),
+                Err(_) => (1, None, PitchClass::new(0))
+            }),
+            ChordSort::Recent => names.sort_by_key(|name| {
+                self.recent_chords.iter().position(|recent| recent == name).unwrap_or(usize::MAX)
+            })
+        }
+    }
+
+    fn use_chord(&mut self, name: &str) {
+        self.recent_chords.retain(|recent| recent != name);
+        self.recent_chords.insert(0, name.to_owned());
+        self.recent_chords.truncate(MAX_RECENT_CHORDS);
+    }
+
+    fn reindex_song(&mut self, id: Id) {
+        if let (Some(index), Some(song)) = (self.song_index.as_mut(), self.songs.iter().find(|s| s.id == id)) {
+            index.update(song);
+        }
+    }
+
+    // songs in the sidebar sorted by name, with the lines that matched the search
+    pub fn search_songs(&mut self) -> Vec<SongMatch> {
+        let index = self.song_index.get_or_insert_with(|| SongIndex::new(&self.songs));
+        let songs = self.songs.iter()
+            .filter(|song| self.show_archived_songs || !song.archived)
+            .sorted_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+        SongQuery::parse(&self.song_search_input).run(index, songs)
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ChordSort {
+    #[default]
+    Name,
+    Root,
+    Quality,
+    Recent
+}
+
+impl ChordSort {
+    pub fn all() -> [ChordSort; 4] {
+        [ChordSort::Name, ChordSort::Root, ChordSort::Quality, ChordSort::Recent]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChordSort::Name => "name",
+            ChordSort::Root => "root",
+            ChordSort::Quality => "quality",
+            ChordSort::Recent => "recently used"
         }
     }
 }
@@ -51,42 +273,450 @@ pub enum Tab {
     Songs,
 }
 
+#[derive(Clone)]
 pub enum Msg {
-    DeleteChord(i32),
-    AddEmptyChord(String),
+    // moves the chord to the trash
+    DeleteChord(Id),
+    // takes the chord out without the trash, reverts adding one
+    RemoveChord(Id),
+    // back from the trash, or wherever it was before
+    RestoreChord(usize, Chord),
+    RestoreFromTrash(Id),
+    PurgeChord(Id),
+    ReturnToTrash(usize, Chord),
+    ConfirmDeleteChord(Id),
+    CancelDeleteChord,
+    AddEmptyChord(String, Instrument),
+    AddChordShape(String, Instrument, Vec<NotePos>),
+    SetChordNotes(Id, Vec<NotePos>),
+    // notes that don't fit on the new instrument are dropped
+    SetChordInstrument(Id, Instrument),
+    // tags, favorite and difficulty, also puts a chord back as it was to revert edits
+    UpdateChord(Chord),
     AddEmptySong(String),
+    AddSong(Song),
+    InsertSong(usize, Song),
+    RemoveSong(Id),
+    DeleteSong(Id),
+    ConfirmDeleteSong(Id),
+    CancelDeleteSong,
+    DuplicateSong(Id),
+    // refused when another song has the name
+    RenameSong(Id, String),
+    SetSongArchived(Id, bool),
+    SetSongText(Id, String),
+    // name, artist, capo and instrument edits
+    UpdateSong(Song),
     SelectChord(String),
-    SelectSong(String),
-    InsertSongPreference(String, Chord)
+    SelectSong(Id),
+    JumpToSongLine(Id, usize),
+    InsertSongPreference(Id, Chord),
+    TransposeSong(Id, i32),
+    Undo,
+    Redo,
+    OpenLibrary(PathBuf),
+    // starts a library in a directory that doesn't hold one yet
+    CreateLibrary(PathBuf),
+    CloseLibrary,
+    // resolve a conflict between edits on disk and in the app, taking one side
+    ReloadLibrary,
+    OverwriteLibrary,
+    // replaces the state with what could be read of the failed load
+    RecoverState,
+    DismissLoadError
 }
 
-fn run_message(state: &mut State, msg: &Msg) {
+impl Msg {
+    // adds, removes or replaces songs, the search index is built again
+    fn replaces_songs(&self) -> bool {
+        matches!(self,
+            Msg::AddEmptySong(_) | Msg::AddSong(_) | Msg::InsertSong(..) | Msg::RemoveSong(_) | Msg::DeleteSong(_)
+            | Msg::DuplicateSong(_) | Msg::Undo | Msg::Redo | Msg::OpenLibrary(_) | Msg::CreateLibrary(_) | Msg::CloseLibrary
+            | Msg::ReloadLibrary | Msg::RecoverState)
+    }
+
+    // the song whose text, name or artist the message edits, only it is indexed again
+    fn edited_song(&self) -> Option<Id> {
+        match self {
+            Msg::SetSongText(id, _) | Msg::RenameSong(id, _) | Msg::TransposeSong(id, _) => Some(*id),
+            Msg::UpdateSong(song) => Some(song.id),
+            _ => None
+        }
+    }
+
+    // edits made continuously by typing or dragging, see History::record
+    pub fn merges_with(&self, other: &Msg) -> bool {
+        match (self, other) {
+            (Msg::SetSongText(a, _), Msg::SetSongText(b, _)) => a == b,
+            (Msg::UpdateSong(a), Msg::UpdateSong(b)) => a.id == b.id,
+            (Msg::SetChordInstrument(a, _), Msg::SetChordInstrument(b, _)) => a == b,
+            (Msg::UpdateChord(a), Msg::UpdateChord(b)) => a.id == b.id,
+            _ => false
+        }
+    }
+}
+
+const MAX_RECENT_LIBRARIES: usize = 8;
+const MAX_RECENT_CHORDS: usize = 50;
+
+// applies the message, undoable ones return a label for the history and the message reverting them
+fn run_message(state: &mut State, msg: &Msg) -> Option<(String, Msg)> {
     match msg {
         Msg::DeleteChord(id) => {
-            state.chords.retain(|chord| chord.id != *id);
+            let pos = state.chords.iter().position(|chord| chord.id == *id)?;
+            let chord = state.chords.remove(pos);
+            state.trash.insert(0, chord.clone());
+            if state.chord_to_delete == Some(*id) {
+                state.chord_to_delete = None;
+            }
+            Some((format!("delete {}", chord.name), Msg::RestoreChord(pos, chord)))
+        }
+        Msg::RemoveChord(id) => {
+            let pos = state.chords.iter().position(|chord| chord.id == *id)?;
+            let chord = state.chords.remove(pos);
+            Some((format!("remove {}", chord.name), Msg::RestoreChord(pos, chord)))
         }
-        Msg::AddEmptyChord(name) => {
-            let last_id = state.chords.last().map(|c| c.id).unwrap_or(0);
-            state.chords.push(Chord::empty(last_id + 1, name.to_owned()));
+        Msg::RestoreChord(pos, chord) => {
+            let trashed = state.trash.iter().position(|c| c.id == chord.id).map(|idx| state.trash.remove(idx));
+            state.chords.insert((*pos).min(state.chords.len()), chord.clone());
+            let inverse = match trashed {
+                Some(_) => Msg::DeleteChord(chord.id),
+                None => Msg::RemoveChord(chord.id)
+            };
+            Some((format!("restore {}", chord.name), inverse))
+        }
+        Msg::RestoreFromTrash(id) => {
+            let chord = state.trash.iter().find(|c| c.id == *id)?.clone();
+            run_message(state, &Msg::RestoreChord(state.chords.len(), chord))
+        }
+        Msg::PurgeChord(id) => {
+            let idx = state.trash.iter().position(|c| c.id == *id)?;
+            let chord = state.trash.remove(idx);
+            Some((format!("delete {} for good", chord.name), Msg::ReturnToTrash(idx, chord)))
+        }
+        Msg::ReturnToTrash(idx, chord) => {
+            state.trash.insert((*idx).min(state.trash.len()), chord.clone());
+            Some((format!("put {} back in the trash", chord.name), Msg::PurgeChord(chord.id)))
+        }
+        Msg::ConfirmDeleteChord(id) => {
+            state.chord_to_delete = Some(*id);
+            None
+        }
+        Msg::CancelDeleteChord => {
+            state.chord_to_delete = None;
+            None
+        }
+        Msg::AddEmptyChord(name, instrument) => {
+            let chord = Chord::empty(name.to_owned(), instrument.clone());
+            let id = chord.id;
+            state.chords.push(chord);
+            Some((format!("add {}", name), Msg::RemoveChord(id)))
+        }
+        Msg::AddChordShape(name, instrument, notes) => {
+            let mut chord = Chord::empty(name.to_owned(), instrument.clone());
+            chord.notes = notes.clone();
+            let id = chord.id;
+            state.chords.push(chord);
+            Some((format!("add voicing of {}", name), Msg::RemoveChord(id)))
+        }
+        Msg::SetChordNotes(id, notes) => {
+            let chord = state.chords.iter_mut().find(|chord| chord.id == *id)?;
+            let old_notes = std::mem::replace(&mut chord.notes, notes.clone());
+            Some((format!("edit notes of {}", chord.name), Msg::SetChordNotes(*id, old_notes)))
+        }
+        Msg::SetChordInstrument(id, instrument) => {
+            let chord = state.chords.iter_mut().find(|chord| chord.id == *id)?;
+            let old = chord.clone();
+            chord.instrument = instrument.clone();
+            chord.notes.retain(|note| instrument.contains(*note));
+            Some((format!("change instrument of {}", chord.name), Msg::UpdateChord(old)))
+        }
+        Msg::UpdateChord(updated) => {
+            let chord = state.chords.iter_mut().find(|chord| chord.id == updated.id)?;
+            let old = std::mem::replace(chord, updated.clone());
+            Some((format!("edit {}", updated.name), Msg::UpdateChord(old)))
         }
         Msg::AddEmptySong(name) => {
-            state.songs.push(Song::empty(name.to_owned()))
+            run_message(state, &Msg::AddSong(Song::empty(name.to_owned())))
+        }
+        Msg::AddSong(song) => {
+            state.songs.push(song.clone());
+            Some((format!("add song {}", song.name), Msg::RemoveSong(song.id)))
+        }
+        Msg::InsertSong(idx, song) => {
+            state.songs.insert((*idx).min(state.songs.len()), song.clone());
+            Some((format!("add song {}", song.name), Msg::RemoveSong(song.id)))
+        }
+        Msg::RemoveSong(id) => {
+            let idx = state.songs.iter().position(|s| s.id == *id)?;
+            let song = state.songs.remove(idx);
+            Some((format!("remove song {}", song.name), Msg::InsertSong(idx, song)))
+        }
+        Msg::DeleteSong(id) => {
+            if state.song_to_delete == Some(*id) {
+                state.song_to_delete = None;
+            }
+            let idx = state.songs.iter().position(|s| s.id == *id)?;
+            let song = state.songs.remove(idx);
+            Some((format!("delete song {}", song.name), Msg::InsertSong(idx, song)))
+        }
+        Msg::ConfirmDeleteSong(id) => {
+            state.song_to_delete = Some(*id);
+            None
+        }
+        Msg::CancelDeleteSong => {
+            state.song_to_delete = None;
+            None
+        }
+        Msg::DuplicateSong(id) => {
+            let idx = state.songs.iter().position(|s| s.id == *id)?;
+            let mut copy = state.songs[idx].clone();
+            copy.id = new_id();
+            copy.name = format!("{} (copy)", state.songs[idx].name);
+            let mut n = 1;
+            while song_name_taken(&state.songs, copy.id, &copy.name) {
+                n += 1;
+                copy.name = format!("{} (copy {})", state.songs[idx].name, n);
+            }
+            state.selected_song = Some(copy.id);
+            let label = format!("duplicate song {}", state.songs[idx].name);
+            state.songs.insert(idx + 1, copy.clone());
+            Some((label, Msg::RemoveSong(copy.id)))
+        }
+        Msg::RenameSong(id, name) => {
+            let name = name.trim();
+            if name.is_empty() || song_name_taken(&state.songs, *id, name) {
+                return None;
+            }
+            let song = state.songs.iter_mut().find(|s| s.id == *id)?;
+            let old_name = std::mem::replace(&mut song.name, name.to_owned());
+            Some((format!("rename {} to {}", old_name, name), Msg::RenameSong(*id, old_name)))
+        }
+        Msg::SetSongArchived(id, archived) => {
+            let song = state.songs.iter_mut().find(|s| s.id == *id)?;
+            song.archived = *archived;
+            let verb = if *archived { "archive" } else { "unarchive" };
+            Some((format!("{} {}", verb, song.name), Msg::SetSongArchived(*id, !archived)))
+        }
+        Msg::SetSongText(id, text) => {
+            let song = state.songs.iter_mut().find(|s| s.id == *id)?;
+            let old_text = std::mem::replace(&mut song.text, text.clone());
+            Some((format!("edit text of {}", song.name), Msg::SetSongText(*id, old_text)))
+        }
+        Msg::UpdateSong(updated) => {
+            let song = state.songs.iter_mut().find(|s| s.id == updated.id)?;
+            let old = std::mem::replace(song, updated.clone());
+            Some((format!("edit song {}", updated.name), Msg::UpdateSong(old)))
         }
         Msg::SelectChord(name) => {
             state.selected_chord = name.to_owned();
+            state.use_chord(name);
+            None
+        }
+        Msg::SelectSong(id) => {
+            state.selected_song = Some(*id);
+            None
         }
-        Msg::SelectSong(name) => {
-            state.selected_song = name.to_owned();
+        Msg::JumpToSongLine(id, line) => {
+            state.selected_song = Some(*id);
+            state.song_jump = Some(*line);
+            None
         }
-        Msg::InsertSongPreference(song_name, chord) => {
-            state.songs.iter_mut().find(|s| s.name == *song_name)
-                .map(|s| s.preferences.insert(chord.name.to_owned(), chord.id.to_owned()));
+        Msg::InsertSongPreference(song_id, chord) => {
+            state.use_chord(&chord.name);
+            let song = state.songs.iter_mut().find(|s| s.id == *song_id)?;
+            let old = song.clone();
+            song.preferences.insert(chord.name.to_owned(), chord.id);
+            Some((format!("pick voicing of {} in {}", chord.name, song.name), Msg::UpdateSong(old)))
+        }
+        Msg::TransposeSong(song_id, semitones) => {
+            let song = state.songs.iter_mut().find(|s| s.id == *song_id)?;
+            let old = song.clone();
+            song.transpose(*semitones);
+            Some((format!("transpose {} by {:+}", song.name, semitones), Msg::UpdateSong(old)))
+        }
+        Msg::Undo => {
+            let mut entry = state.history.done.pop()?;
+            if let Some((_, redo)) = run_message(state, &entry.inverse) {
+                entry.forward = redo;
+                state.history.undone.push(entry);
+            }
+            None
+        }
+        Msg::Redo => {
+            let mut entry = state.history.undone.pop()?;
+            if let Some((_, inverse)) = run_message(state, &entry.forward) {
+                entry.inverse = inverse;
+                state.history.done.push(entry);
+            }
+            None
+        }
+        Msg::OpenLibrary(path) | Msg::CreateLibrary(path) => {
+            // changes still in memory go to the library being left
+            if let Some(library) = state.library.as_mut() {
+                if let Err(error) = library.write(&state.chords, &state.songs) {
+                    state.library_status = format!("can't save {}: {}", library.path.display(), error);
+                    return None;
+                }
+            }
+            let opened = match msg {
+                Msg::CreateLibrary(_) => {
+                    // a new library only takes over what the app storage holds
+                    let (chords, songs) = match state.library {
+                        Some(_) => (vec![], vec![]),
+                        None => (state.chords.clone(), state.songs.clone())
+                    };
+                    Library::create(path, &chords, &songs).map(|library| (library, chords, songs))
+                }
+                _ => {
+                    let (mut chords, mut songs) = (vec![], vec![]);
+                    Library::open(path, &mut chords, &mut songs).map(|library| (library, chords, songs))
+                }
+            };
+            match opened {
+                Ok((library, chords, songs)) => {
+                    // kept from the first library on, switching between libraries doesn't touch it
+                    state.stored_collection.get_or_insert_with(|| (state.chords.clone(), state.songs.clone()));
+                    state.chords = chords;
+                    state.songs = songs;
+                    state.library = Some(library);
+                    state.library_path = Some(path.clone());
+                    state.recent_libraries.retain(|p| p != path);
+                    state.recent_libraries.insert(0, path.clone());
+                    state.recent_libraries.truncate(MAX_RECENT_LIBRARIES);
+                    state.library_status = format!("opened {}", path.display());
+                    state.history.clear();
+                }
+                Err(error) => state.library_status = format!("can't open {}: {}", path.display(), error)
+            }
+            None
+        }
+        Msg::CloseLibrary => {
+            // the library keeps its files, the app storage gets back what it held before
+            if let Some(mut library) = state.library.take() {
+                if let Err(error) = library.write(&state.chords, &state.songs) {
+                    state.library_status = format!("can't save {}: {}", library.path.display(), error);
+                    state.library = Some(library);
+                    return None;
+                }
+                state.library_status = format!("closed {}", library.path.display());
+            }
+            if let Some((chords, songs)) = state.stored_collection.take() {
+                state.chords = chords;
+                state.songs = songs;
+                state.history.clear();
+            }
+            state.library_path = None;
+            None
+        }
+        Msg::ReloadLibrary => {
+            let library = state.library.as_mut()?;
+            match library.reload() {
+                Ok((chords, songs)) => {
+                    state.chords = chords;
+                    state.songs = songs;
+                    // recorded steps point at what was replaced
+                    state.history.clear();
+                    state.library_status = "reloaded, the edits made in the app are dropped".to_owned();
+                }
+                Err(error) => state.library_status = format!("can't reload library: {}", error)
+            }
+            None
+        }
+        Msg::OverwriteLibrary => {
+            let library = state.library.as_mut()?;
+            state.library_status = match library.overwrite(&state.chords, &state.songs) {
+                Ok(()) => "saved over the changes on disk".to_owned(),
+                Err(error) => format!("can't save library: {}", error)
+            };
+            None
+        }
+        Msg::RecoverState => {
+            if let Some(recovered) = state.load_error.take().and_then(|error| error.recovered) {
+                *state = *recovered;
+                // a library keeps its own files, they win over the copy in the app storage
+                if let Some(path) = state.library_path.clone() {
+                    run_message(state, &Msg::OpenLibrary(path));
+                }
+            }
+            None
+        }
+        Msg::DismissLoadError => {
+            state.load_error = None;
+            None
         }
     }
 }
 
-pub fn run_messages(state: &mut State, messages: &Vec<Msg>) {
+pub fn run_messages(state: &mut State, messages: &[Msg]) {
     for message in messages.iter() {
-        run_message(state, message)
+        if let Some((label, inverse)) = run_message(state, message) {
+            state.history.record(label, message.clone(), inverse);
+        }
+        if message.replaces_songs() {
+            state.song_index = None;
+        }
+        if let Some(id) = message.edited_song() {
+            state.reindex_song(id);
+        }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use super::*;
+
+    fn song_names(state: &State) -> Vec<&str> {
+        state.songs.iter().map(|song| song.name.as_str()).collect()
+    }
+
+    #[test]
+    fn closing_a_library_brings_back_the_app_storage() {
+        let path = env::temp_dir().join(format!("rusty-guitar-library-{}", new_id()));
+        let mut shared = State::default();
+        shared.songs[0].name = "shared".to_owned();
+        run_message(&mut shared, &Msg::OpenLibrary(path.clone()));
+        assert!(shared.library.is_none());
+        run_message(&mut shared, &Msg::CreateLibrary(path.clone()));
+        assert!(shared.library.is_some());
+
+        let mut own = State::default();
+        own.songs[0].name = "mine".to_owned();
+        run_message(&mut own, &Msg::OpenLibrary(path.clone()));
+        assert_eq!(song_names(&own), vec!["shared"]);
+        // what is saved while the library is open still holds the app storage songs
+        let saved: State = serde_json::from_str(&serde_json::to_string(&own).unwrap()).unwrap();
+        assert_eq!(saved.stored_collection.map(|(_, songs)| songs[0].name.clone()), Some("mine".to_owned()));
+
+        run_message(&mut own, &Msg::CloseLibrary);
+        assert_eq!(song_names(&own), vec!["mine"]);
+        assert!(own.stored_collection.is_none());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn edits_update_the_song_index() {
+        let mut state = State::default();
+        let id = state.songs[0].id;
+        let search = |state: &mut State, input: &str| {
+            state.song_search_input = input.to_owned();
+            state.search_songs().len()
+        };
+        assert_eq!(search(&mut state, "chord:Dmaj7 test"), 1);
+        run_messages(&mut state, &[Msg::SelectChord("Dmaj7".to_owned())]);
+        assert!(state.song_index.is_some());
+
+        run_messages(&mut state, &[Msg::SetSongText(id, "Em\nriver".to_owned())]);
+        assert!(state.song_index.is_some());
+        assert_eq!(search(&mut state, "starts"), 0);
+        assert_eq!(search(&mut state, "chord:Em riv"), 1);
+        assert_eq!(search(&mut state, "key:Em"), 1);
+        run_messages(&mut state, &[Msg::RenameSong(id, "Flow".to_owned())]);
+        assert_eq!(search(&mut state, "flow river"), 1);
+
+        run_messages(&mut state, &[Msg::DeleteSong(id)]);
+        assert_eq!(search(&mut state, "river"), 0);
+    }
+}
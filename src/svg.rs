@@ -0,0 +1,125 @@
+// Standalone svg documents of chord diagrams, for handouts and web pages.
+// Shapes come from the same layout the egui widget paints, so both look alike.
+
+use crate::diagram::{Anchor, ChordLayout, DiagramShape, FRET_THICKNESS};
+use crate::format::number;
+use crate::models::Chord;
+
+pub struct SvgStyle {
+    // 1.0 is the size of the diagram on screen
+    pub scale: f32,
+    pub background: String,
+    pub foreground: String,
+    pub font_family: String
+}
+
+impl Default for SvgStyle {
+    fn default() -> Self {
+        SvgStyle {
+            scale: 1.0,
+            background: "#ffffff".to_owned(),
+            foreground: "#000000".to_owned(),
+            font_family: "sans-serif".to_owned()
+        }
+    }
+}
+
+pub fn chord_svg(chord: &Chord, capo: i32, style: &SvgStyle) -> String {
+    let layout = ChordLayout::new(&chord.notes, &chord.instrument, capo);
+    let mut lines = vec![
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+            number(layout.width * style.scale), number(layout.height * style.scale), number(layout.width), number(layout.height)
+        ),
+        format!("  <title>{}</title>", escape(&chord.name)),
+        format!(r#"  <rect width="{}" height="{}" fill="{}"/>"#, number(layout.width), number(layout.height), escape(&style.background))
+    ];
+    for shape in layout.shapes.iter() {
+        lines.push(format!("  {}", shape_element(shape, style)));
+    }
+    lines.push("</svg>".to_owned());
+    lines.join("\n") + "\n"
+}
+
+fn shape_element(shape: &DiagramShape, style: &SvgStyle) -> String {
+    match shape {
+        DiagramShape::Line { from, to, thickness } => format!(
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}"/>"#,
+            number(from.0), number(from.1), number(to.0), number(to.1), escape(&style.foreground), number(*thickness)
+        ),
+        DiagramShape::Circle { center, radius } => format!(
+            r#"<circle cx="{}" cy="{}" r="{}" fill="{}" stroke="{}" stroke-width="{}"/>"#,
+            number(center.0), number(center.1), number(*radius), escape(&style.background), escape(&style.foreground), number(FRET_THICKNESS)
+        ),
+        DiagramShape::Text { pos, text, size, anchor } => {
+            let text_anchor = match anchor {
+                Anchor::Center => "middle",
+                Anchor::Left => "start"
+            };
+            format!(
+                r#"<text x="{}" y="{}" font-family="{}" font-size="{}" text-anchor="{}" dominant-baseline="central" fill="{}">{}</text>"#,
+                number(pos.0), number(pos.1), escape(&style.font_family), number(*size), text_anchor, escape(&style.foreground), escape(text)
+            )
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use super::*;
+    use crate::instrument::Instrument;
+
+    // run with UPDATE_GOLDEN=1 to rewrite the files after an intended change of the look
+    fn assert_golden(name: &str, svg: &str) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.svg", name));
+        if std::env::var("UPDATE_GOLDEN").is_ok() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, svg).unwrap();
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing golden file {}", path.display()));
+        assert_eq!(svg, expected, "{} differs from {}", name, path.display());
+    }
+
+    fn chord(name: &str, notes: Vec<(i32, i32)>, instrument: Instrument) -> Chord {
+        Chord { notes, ..Chord::empty(name.to_owned(), instrument) }
+    }
+
+    #[test]
+    fn open_chord() {
+        let c = chord("C", vec![(0, 1), (1, 2), (0, 3), (2, 4), (3, 5)], Instrument::guitar());
+        assert_golden("open_c", &chord_svg(&c, 0, &SvgStyle::default()));
+    }
+
+    #[test]
+    fn barre_chord_up_the_neck() {
+        let b7 = chord("Bm7", vec![(7, 1), (7, 2), (7, 3), (9, 4), (7, 5)], Instrument::guitar());
+        assert_golden("barre_bm7", &chord_svg(&b7, 0, &SvgStyle::default()));
+    }
+
+    #[test]
+    fn shape_behind_a_capo() {
+        let am = chord("Am", vec![(0, 1), (1, 2), (2, 3), (2, 4), (0, 5)], Instrument::guitar());
+        assert_golden("capo_am", &chord_svg(&am, 3, &SvgStyle::default()));
+    }
+
+    #[test]
+    fn ukulele_with_custom_style() {
+        let style = SvgStyle {
+            scale: 2.0,
+            background: "#202020".to_owned(),
+            foreground: "#f0e0c0".to_owned(),
+            font_family: "Georgia, serif".to_owned()
+        };
+        let c = chord("C & friends", vec![(0, 4), (0, 3), (0, 2), (3, 1)], Instrument::ukulele());
+        assert_golden("ukulele_styled", &chord_svg(&c, 0, &style));
+    }
+}
@@ -0,0 +1,513 @@
+// chord symbol grammar, the way chords are written above lyrics:
+//
+//   symbol      := root quality? extension? modifier* ("/" bass)?
+//   root, bass  := letter accidental*              C, F#, Bb, E♭
+//   quality     := maj | Maj | M | Δ | m | min | - | dim | ° | aug | + | ø | o (only as o7,
+//                  so words like "Go" in lyrics don't read as chords)
+//   extension   := 2 | 5 | 6 | 69 | 6/9 | 7 | 9 | 11 | 13     C2 is read as Csus2
+//   modifier    := alteration | addition | suspension | omission | "(" modifier ("," modifier)* ")"
+//   alteration  := (b | # | - | +) (5 | 9 | 11 | 13)  b9, #11, +5
+//   addition    := add (2 | 4 | 6 | 9 | 11 | 13)     also bare degrees inside parentheses: C(9)
+//   suspension  := sus (2 | 4)?                       sus alone means sus4
+//   omission    := (no | omit) (3 | 5)
+//
+// maj/M/Δ right before the extension (or inside parentheses after a minor quality,
+// as in Cm(maj7)) makes the seventh major.
+
+use std::fmt;
+use itertools::Itertools;
+use crate::pitch::{Note, PitchClass};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Quality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    HalfDiminished,
+    Power
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Extension {
+    Sixth,
+    SixNine,
+    Seventh,
+    Ninth,
+    Eleventh,
+    Thirteenth
+}
+
+// altered degree: b5, #9, #11, b13
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alteration {
+    pub degree: u8,
+    pub accidental: i8
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChordSymbol {
+    pub root: Note,
+    pub quality: Quality,
+    pub extension: Option<Extension>,
+    pub major_seventh: bool,
+    pub alterations: Vec<Alteration>,
+    pub additions: Vec<u8>,
+    // 2 or 4
+    pub suspension: Option<u8>,
+    pub omissions: Vec<u8>,
+    pub bass: Option<Note>,
+    // everything between the root and the bass exactly as it was written
+    pub suffix: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChordParseError {
+    // byte offset in the parsed string
+    pub position: usize,
+    pub message: String
+}
+
+impl fmt::Display for ChordParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        if self.rest().starts_with(prefix) {
+            self.pos += prefix.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_any(&mut self, prefixes: &[&str]) -> bool {
+        prefixes.iter().any(|p| self.eat(p))
+    }
+
+    fn number(&mut self) -> Option<u8> {
+        let digits = self.rest().chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        let value = self.rest()[..digits].parse::<u8>().ok()?;
+        self.pos += digits;
+        Some(value)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ChordParseError> {
+        Err(ChordParseError { position: self.pos, message: message.to_owned() })
+    }
+
+    fn note(&mut self) -> Option<Note> {
+        let (note, consumed) = Note::parse_prefix(self.rest())?;
+        self.pos += consumed;
+        Some(note)
+    }
+
+    // maj/M/Δ in front of a seventh-type extension
+    fn major_seventh_marker(&mut self) -> bool {
+        let start = self.pos;
+        if self.eat_any(&["maj", "Maj", "MA", "ma", "M", "Δ"]) {
+            if matches!(self.peek(), Some('7' | '9' | '1')) {
+                return true;
+            }
+            self.pos = start;
+        }
+        false
+    }
+
+    fn extension(&mut self) -> Result<Option<Extension>, ChordParseError> {
+        let start = self.pos;
+        let extension = match self.number() {
+            None => return Ok(None),
+            Some(6) if self.eat("/9") => Extension::SixNine,
+            Some(69) => Extension::SixNine,
+            Some(6) => Extension::Sixth,
+            Some(7) => Extension::Seventh,
+            Some(9) => Extension::Ninth,
+            Some(11) => Extension::Eleventh,
+            Some(13) => Extension::Thirteenth,
+            Some(_) => {
+                self.pos = start;
+                return self.error("unknown extension");
+            }
+        };
+        Ok(Some(extension))
+    }
+}
+
+impl ChordSymbol {
+    pub fn parse(input: &str) -> Result<ChordSymbol, ChordParseError> {
+        let mut p = Parser { input, pos: 0 };
+
+        let root = match p.note() {
+            Some(root) => root,
+            None => return p.error("expected a note name A-G")
+        };
+        let suffix_start = p.pos;
+
+        let mut major_seventh = false;
+        let quality = if p.eat("ø") {
+            Quality::HalfDiminished
+        } else if p.eat_any(&["dim", "°"]) || p.rest().starts_with("o7") && p.eat("o") {
+            Quality::Diminished
+        } else if p.eat_any(&["aug", "+"]) {
+            Quality::Augmented
+        } else if p.major_seventh_marker() {
+            major_seventh = true;
+            Quality::Major
+        } else if p.eat_any(&["maj", "Maj", "M"]) {
+            Quality::Major
+        } else if p.eat("Δ") {
+            // triangle alone is a major seventh chord
+            major_seventh = true;
+            Quality::Major
+        } else if p.eat_any(&["min", "m", "-"]) {
+            major_seventh = p.major_seventh_marker();
+            Quality::Minor
+        } else {
+            Quality::Major
+        };
+
+        let plain_major = quality == Quality::Major && !major_seventh;
+        let mut suspension = None;
+        let (quality, mut extension) = if plain_major && p.eat("5") {
+            (Quality::Power, None)
+        } else if plain_major && p.rest().starts_with('2') && !p.rest()[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            p.pos += 1;
+            suspension = Some(2);
+            (quality, None)
+        } else {
+            (quality, p.extension()?)
+        };
+        if major_seventh && extension.is_none() {
+            extension = Some(Extension::Seventh);
+        }
+        if quality == Quality::HalfDiminished && extension.is_none() {
+            extension = Some(Extension::Seventh);
+        }
+
+        let mut symbol = ChordSymbol {
+            root,
+            quality,
+            extension,
+            major_seventh,
+            alterations: vec![],
+            additions: vec![],
+            suspension,
+            omissions: vec![],
+            bass: None,
+            suffix: "".to_owned()
+        };
+
+        let mut in_parens = false;
+        loop {
+            match p.peek() {
+                None | Some('/') => break,
+                Some('(') if !in_parens => {
+                    p.pos += 1;
+                    in_parens = true;
+                }
+                Some(')') if in_parens => {
+                    p.pos += 1;
+                    in_parens = false;
+                }
+                Some(',') | Some(' ') if in_parens => p.pos += 1,
+                Some(_) => symbol.modifier(&mut p, in_parens)?
+            }
+        }
+        if in_parens {
+            return p.error("missing closing parenthesis");
+        }
+        symbol.suffix = input[suffix_start..p.pos].to_owned();
+
+        if p.eat("/") {
+            match p.note() {
+                Some(bass) => symbol.bass = Some(bass),
+                None => return p.error("expected a bass note after /")
+            }
+            if p.pos < input.len() {
+                return p.error("unexpected text after the bass note");
+            }
+        }
+
+        Ok(symbol)
+    }
+
+    fn modifier(&mut self, p: &mut Parser, in_parens: bool) -> Result<(), ChordParseError> {
+        let start = p.pos;
+        if p.eat("sus") {
+            match p.number() {
+                None => self.suspension = Some(4),
+                Some(degree @ (2 | 4)) => self.suspension = Some(degree),
+                Some(_) => {
+                    p.pos = start + 3;
+                    return p.error("sus can only be 2 or 4");
+                }
+            }
+        } else if p.eat("add") {
+            match p.number() {
+                Some(degree @ (2 | 4 | 6 | 9 | 11 | 13)) => self.additions.push(degree),
+                _ => {
+                    p.pos = start + 3;
+                    return p.error("expected 2, 4, 6, 9, 11 or 13 after add");
+                }
+            }
+        } else if p.eat_any(&["omit", "no"]) {
+            let degree_start = p.pos;
+            match p.number() {
+                Some(degree @ (3 | 5)) => self.omissions.push(degree),
+                _ => {
+                    p.pos = degree_start;
+                    return p.error("only the 3rd or the 5th can be omitted");
+                }
+            }
+        } else if in_parens && p.major_seventh_marker() {
+            self.major_seventh = true;
+            p.number();
+            if self.extension.is_none() {
+                self.extension = Some(Extension::Seventh);
+            }
+        } else if let Some(accidental) = match p.peek() {
+            Some('b' | '♭' | '-') => Some(-1),
+            Some('#' | '♯' | '+') => Some(1),
+            _ => None
+        } {
+            p.pos += p.peek().unwrap().len_utf8();
+            match p.number() {
+                Some(degree @ (5 | 9 | 11 | 13)) => self.alterations.push(Alteration { degree, accidental }),
+                _ => {
+                    p.pos = start;
+                    return p.error("expected an altered 5, 9, 11 or 13");
+                }
+            }
+        } else if in_parens {
+            match p.number() {
+                Some(degree @ (2 | 4 | 6 | 9 | 11 | 13)) => self.additions.push(degree),
+                _ => {
+                    p.pos = start;
+                    return p.error("unexpected chord modifier");
+                }
+            }
+        } else {
+            return p.error("unexpected chord modifier");
+        }
+        Ok(())
+    }
+
+    // semitones above the root for every tone of the chord
+    pub fn intervals(&self) -> Vec<i32> {
+        self.degrees().into_iter().map(|(_, semitones)| semitones).collect()
+    }
+
+    // every tone as its degree, 9 rather than 2 for the ninth, and its semitones above the root within the octave
+    pub fn degrees(&self) -> Vec<(u8, i32)> {
+        // (degree, semitones, altered): omissions take out a degree, never a tone an alteration put in
+        let mut tones: Vec<(u8, i32, bool)> = match self.quality {
+            Quality::Major => vec![(1, 0, false), (3, 4, false), (5, 7, false)],
+            Quality::Minor => vec![(1, 0, false), (3, 3, false), (5, 7, false)],
+            Quality::Diminished | Quality::HalfDiminished => vec![(1, 0, false), (3, 3, false), (5, 6, false)],
+            Quality::Augmented => vec![(1, 0, false), (3, 4, false), (5, 8, false)],
+            Quality::Power => vec![(1, 0, false), (5, 7, false)]
+        };
+
+        if let Some(sus) = self.suspension {
+            tones.retain(|(degree, _, _)| *degree != 3);
+            tones.push((sus, if sus == 2 { 2 } else { 5 }, false));
+        }
+
+        let seventh = if self.major_seventh {
+            11
+        } else if self.quality == Quality::Diminished {
+            9
+        } else {
+            10
+        };
+        match self.extension {
+            None => (),
+            Some(Extension::Sixth) => tones.push((6, 9, false)),
+            Some(Extension::SixNine) => tones.extend([(6, 9, false), (9, 2, false)]),
+            Some(Extension::Seventh) => tones.push((7, seventh, false)),
+            Some(Extension::Ninth) => tones.extend([(7, seventh, false), (9, 2, false)]),
+            Some(Extension::Eleventh) => tones.extend([(7, seventh, false), (9, 2, false), (11, 5, false)]),
+            Some(Extension::Thirteenth) => tones.extend([(7, seventh, false), (9, 2, false), (11, 5, false), (13, 9, false)])
+        }
+
+        for alteration in self.alterations.iter() {
+            let natural = match alteration.degree {
+                5 => 7,
+                9 => 2,
+                11 => 5,
+                _ => 9
+            };
+            tones.retain(|(degree, _, _)| *degree != alteration.degree);
+            tones.push((alteration.degree, (natural + alteration.accidental as i32).rem_euclid(12), true));
+        }
+
+        for addition in self.additions.iter() {
+            tones.push((*addition, match addition {
+                2 | 9 => 2,
+                4 | 11 => 5,
+                _ => 9
+            }, false));
+        }
+
+        for omission in self.omissions.iter() {
+            tones.retain(|(degree, _, altered)| *altered || degree != omission);
+        }
+
+        tones.into_iter().map(|(degree, semitones, _)| (degree, semitones)).unique_by(|(_, semitones)| *semitones).collect()
+    }
+
+    // tones a voicing can leave out without changing what the chord is:
+    // the unaltered fifth, plus the inner extensions of 11th and 13th chords
+    pub fn optional_intervals(&self) -> Vec<i32> {
+        let mut optional = vec![];
+        if !self.alterations.iter().any(|a| a.degree == 5) && self.quality != Quality::Power {
+            optional.push(7);
+        }
+        match self.extension {
+            Some(Extension::Eleventh) if self.quality == Quality::Major => optional.extend([4, 2]),
+            Some(Extension::Eleventh) => optional.push(2),
+            Some(Extension::Thirteenth) => optional.extend([2, 5]),
+            _ => ()
+        }
+        optional
+    }
+
+    pub fn tones(&self) -> Vec<PitchClass> {
+        self.intervals().iter()
+            .map(|i| self.root.pitch_class().transpose(*i))
+            .chain(self.bass.map(|b| b.pitch_class()))
+            .unique()
+            .collect()
+    }
+
+    // tones a voicing has to contain to still be this chord, the slash bass included
+    pub fn required_tones(&self) -> Vec<PitchClass> {
+        let optional = self.optional_intervals();
+        self.intervals().iter()
+            .filter(|i| !optional.contains(i))
+            .map(|i| self.root.pitch_class().transpose(*i))
+            .chain(self.bass.map(|b| b.pitch_class()))
+            .unique()
+            .collect()
+    }
+
+    pub fn bass_tone(&self) -> PitchClass {
+        self.bass.unwrap_or(self.root).pitch_class()
+    }
+
+    // moves root and bass, the suffix is kept the way it was written
+    pub fn transpose(&self, semitones: i32, prefer_flats: bool) -> ChordSymbol {
+        ChordSymbol {
+            root: self.root.transpose(semitones, prefer_flats),
+            bass: self.bass.map(|b| b.transpose(semitones, prefer_flats)),
+            ..self.clone()
+        }
+    }
+
+    // same sounding chord regardless of spelling: C#m7 == Dbmin7, C/C == C
+    pub fn is_equivalent(&self, other: &ChordSymbol) -> bool {
+        self.root.pitch_class() == other.root.pitch_class()
+            && self.bass_tone() == other.bass_tone()
+            && self.intervals().into_iter().sorted().eq(other.intervals().into_iter().sorted())
+    }
+}
+
+impl fmt::Display for ChordSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bass {
+            Some(bass) => write!(f, "{}{}/{}", self.root, self.suffix, bass),
+            None => write!(f, "{}{}", self.root, self.suffix)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pitch::Letter;
+
+    fn sorted_intervals(name: &str) -> Vec<i32> {
+        let symbol = ChordSymbol::parse(name).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        symbol.intervals().into_iter().sorted().collect()
+    }
+
+    #[test]
+    fn intervals() {
+        let table: &[(&str, &[i32])] = &[
+            ("C", &[0, 4, 7]),
+            ("Cm", &[0, 3, 7]),
+            ("C-", &[0, 3, 7]),
+            ("C5", &[0, 7]),
+            ("C2", &[0, 2, 7]),
+            ("Csus2", &[0, 2, 7]),
+            ("Csus", &[0, 5, 7]),
+            ("Cdim", &[0, 3, 6]),
+            ("Co7", &[0, 3, 6, 9]),
+            ("Cø", &[0, 3, 6, 10]),
+            ("Cm7b5", &[0, 3, 6, 10]),
+            ("Caug", &[0, 4, 8]),
+            ("C+7", &[0, 4, 8, 10]),
+            ("C6", &[0, 4, 7, 9]),
+            ("C6/9", &[0, 2, 4, 7, 9]),
+            ("C7", &[0, 4, 7, 10]),
+            ("Cmaj7", &[0, 4, 7, 11]),
+            ("CΔ", &[0, 4, 7, 11]),
+            ("Cm(maj7)", &[0, 3, 7, 11]),
+            ("C9", &[0, 2, 4, 7, 10]),
+            ("C11", &[0, 2, 4, 5, 7, 10]),
+            ("C13", &[0, 2, 4, 5, 7, 9, 10]),
+            ("Cadd9", &[0, 2, 4, 7]),
+            ("C(9)", &[0, 2, 4, 7]),
+            ("C7b9", &[0, 1, 4, 7, 10]),
+            ("C7#5", &[0, 4, 8, 10]),
+            ("C7no3", &[0, 7, 10]),
+            ("C7(omit5)", &[0, 4, 10]),
+            // omissions take out the natural degree, never an altered tone
+            ("C7#11no5", &[0, 4, 6, 10]),
+            ("C7#9no3", &[0, 3, 7, 10]),
+            ("C7b5no5", &[0, 4, 6, 10]),
+            ("Cm7no5", &[0, 3, 10]),
+            ("C/E", &[0, 4, 7]),
+            ("F#m7/C#", &[0, 3, 7, 10])
+        ];
+        for (name, expected) in table {
+            assert_eq!(sorted_intervals(name), *expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn spelling_is_kept() {
+        let symbol = ChordSymbol::parse("Bbmaj7/D").unwrap();
+        assert_eq!(symbol.root, Note::new(Letter::B, -1));
+        assert_eq!(symbol.suffix, "maj7");
+        assert_eq!(symbol.to_string(), "Bbmaj7/D");
+        assert!(symbol.is_equivalent(&ChordSymbol::parse("A#Δ/D").unwrap()));
+    }
+
+    #[test]
+    fn lyric_words_are_rejected() {
+        for word in ["Go", "Be", "Dad", "Each", "Gone", "Baby", "Add", "Car", "Fine", "Ask", "C21", "C7x", "Cadd3", "Cno7", "C(9", "C/", "C/H", "am", "hello"] {
+            assert!(ChordSymbol::parse(word).is_err(), "{} reads as a chord", word);
+        }
+    }
+}
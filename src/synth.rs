@@ -0,0 +1,208 @@
+// Plucked string synthesis for hearing chord shapes. Every string is a Karplus-Strong delay
+// line filled with noise and averaged as it circulates, which sounds like a decaying pluck.
+// Rendering is offline into samples, played by audio.rs or written as a wav file.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use crate::chord::NotePos;
+use crate::format::file_name;
+use crate::models::Chord;
+use crate::tuning::Tuning;
+
+pub const SAMPLE_RATE: u32 = 44100;
+// how long each string rings
+const NOTE_SECONDS: f32 = 2.5;
+// per pass of the delay line, closer to 1 rings longer
+const DECAY: f32 = 0.996;
+const PEAK: f32 = 0.8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum PlayStyle {
+    // low string to high string
+    #[default]
+    StrumDown,
+    StrumUp,
+    Arpeggio,
+    Block
+}
+
+impl PlayStyle {
+    pub fn all() -> [PlayStyle; 4] {
+        [PlayStyle::StrumDown, PlayStyle::StrumUp, PlayStyle::Arpeggio, PlayStyle::Block]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayStyle::StrumDown => "strum down",
+            PlayStyle::StrumUp => "strum up",
+            PlayStyle::Arpeggio => "arpeggio",
+            PlayStyle::Block => "block"
+        }
+    }
+
+    // seconds between two strings
+    fn spacing(&self) -> f32 {
+        match self {
+            PlayStyle::StrumDown | PlayStyle::StrumUp => 0.03,
+            PlayStyle::Arpeggio => 0.3,
+            PlayStyle::Block => 0.0
+        }
+    }
+}
+
+// mono samples of the shape, the capo is added to the stored frets like everywhere else
+pub fn render_chord(notes: &[NotePos], tuning: &Tuning, capo: i32, style: PlayStyle, sample_rate: u32) -> Vec<f32> {
+    // the highest string number is the lowest string
+    let mut frequencies = notes.iter()
+        .map(|(fret, string)| (*string, tuning.pitch_at((fret + capo, *string))))
+        .filter_map(|(string, pitch)| pitch.map(|p| (string, p.frequency())))
+        .collect::<Vec<_>>();
+    frequencies.sort_by_key(|(string, _)| -string);
+    if style == PlayStyle::StrumUp {
+        frequencies.reverse();
+    }
+
+    let offset = (style.spacing() * sample_rate as f32) as usize;
+    let note_length = (NOTE_SECONDS * sample_rate as f32) as usize;
+    let mut samples = vec![0.0; note_length + offset * frequencies.len().saturating_sub(1)];
+    for (idx, (string, frequency)) in frequencies.iter().enumerate() {
+        let start = idx * offset;
+        let pluck = pluck(*frequency, note_length, sample_rate, *string as u32);
+        for (sample, value) in samples[start..].iter_mut().zip(pluck) {
+            *sample += value;
+        }
+    }
+    normalize(&mut samples);
+    samples
+}
+
+// one string: a delay line one period long, filled with noise and low passed on every pass
+pub fn pluck(frequency: f32, length: usize, sample_rate: u32, seed: u32) -> Vec<f32> {
+    let period = ((sample_rate as f32 / frequency).round() as usize).max(2);
+    let mut noise = Noise(seed.wrapping_mul(0x9e37_79b9) | 1);
+    let mut line = (0..period).map(|_| noise.next()).collect::<Vec<_>>();
+    let mut samples = Vec::with_capacity(length);
+    for idx in 0..length {
+        let position = idx % period;
+        let value = line[position];
+        samples.push(value);
+        line[position] = DECAY * 0.5 * (value + line[(position + 1) % period]);
+    }
+    samples
+}
+
+fn normalize(samples: &mut [f32]) {
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if peak > 0.0 {
+        for sample in samples.iter_mut() {
+            *sample *= PEAK / peak;
+        }
+    }
+}
+
+// a folder gets a file named after the chord and the style
+pub fn export_path(chord: &Chord, style: PlayStyle, path: &Path) -> io::Result<PathBuf> {
+    let file = match path.is_dir() {
+        true => path.join(format!("{} {}.wav", file_name(&chord.name), style.name())),
+        false => path.to_path_buf()
+    };
+    let samples = render_chord(&chord.notes, &chord.instrument.tuning, 0, style, SAMPLE_RATE);
+    fs::write(&file, wav_bytes(&samples, SAMPLE_RATE))?;
+    Ok(file)
+}
+
+// 16 bit mono pcm
+pub fn wav_bytes(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_length = samples.len() as u32 * 2;
+    let mut bytes = Vec::with_capacity(44 + data_length as usize);
+    bytes.extend(b"RIFF");
+    bytes.extend((36 + data_length).to_le_bytes());
+    bytes.extend(b"WAVEfmt ");
+    bytes.extend(16u32.to_le_bytes());
+    // pcm, one channel
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(sample_rate.to_le_bytes());
+    bytes.extend((sample_rate * 2).to_le_bytes());
+    bytes.extend(2u16.to_le_bytes());
+    bytes.extend(16u16.to_le_bytes());
+    bytes.extend(b"data");
+    bytes.extend(data_length.to_le_bytes());
+    for sample in samples {
+        bytes.extend(((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+    }
+    bytes
+}
+
+// xorshift, the same seed gives the same pluck so renders can be compared
+struct Noise(u32);
+
+impl Noise {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the shift at which the signal best matches itself, up to 20 ms
+    fn measured_frequency(samples: &[f32], sample_rate: u32) -> f32 {
+        let window = &samples[..4096];
+        let correlation = |lag: usize| window.iter().zip(&samples[lag..]).map(|(a, b)| a * b).sum::<f32>();
+        let period = (20..sample_rate as usize / 50).max_by(|a, b| correlation(*a).total_cmp(&correlation(*b))).unwrap();
+        sample_rate as f32 / period as f32
+    }
+
+    #[test]
+    fn pluck_sounds_at_the_string_pitch() {
+        for frequency in [82.41, 110.0, 329.63] {
+            let samples = pluck(frequency, SAMPLE_RATE as usize, SAMPLE_RATE, 1);
+            let measured = measured_frequency(&samples, SAMPLE_RATE);
+            assert!((measured / frequency - 1.0).abs() < 0.01, "{} sounds at {}", frequency, measured);
+        }
+    }
+
+    #[test]
+    fn pluck_decays() {
+        let samples = pluck(220.0, SAMPLE_RATE as usize * 2, SAMPLE_RATE, 1);
+        let energy = |part: &[f32]| part.iter().map(|s| s * s).sum::<f32>();
+        let second = SAMPLE_RATE as usize;
+        assert!(energy(&samples[second..]) < energy(&samples[..second]) / 4.0);
+    }
+
+    #[test]
+    fn strums_start_each_string_later() {
+        let tuning = Tuning::standard();
+        let open_e = [(0, 6), (2, 5), (2, 4), (1, 3), (0, 2), (0, 1)];
+        let block = render_chord(&open_e, &tuning, 0, PlayStyle::Block, SAMPLE_RATE);
+        let strum = render_chord(&open_e, &tuning, 0, PlayStyle::StrumDown, SAMPLE_RATE);
+        let arpeggio = render_chord(&open_e, &tuning, 0, PlayStyle::Arpeggio, SAMPLE_RATE);
+        assert!(block.len() < strum.len() && strum.len() < arpeggio.len());
+        let peak = block.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - PEAK).abs() < 1e-4);
+    }
+
+    #[test]
+    fn muted_chord_is_silent() {
+        assert!(render_chord(&[], &Tuning::standard(), 0, PlayStyle::StrumDown, SAMPLE_RATE).iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn wav_header() {
+        let samples = render_chord(&[(3, 5), (2, 4), (0, 3), (1, 2), (0, 1)], &Tuning::standard(), 0, PlayStyle::StrumUp, 8000);
+        let wav = wav_bytes(&samples, 8000);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()) as usize, wav.len() - 8);
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 8000);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize, samples.len() * 2);
+    }
+}
@@ -0,0 +1,171 @@
+use std::hash::Hash;
+use eframe::egui::*;
+use itertools::Itertools;
+use serde::{Serialize, Deserialize};
+use crate::chord::NotePos;
+use crate::pitch::{ParsePitchError, Pitch};
+
+const CUSTOM_TUNING_NAME: &str = "Custom";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tuning {
+    pub name: String,
+    // open strings in the order tunings are usually written down: the lowest string first,
+    // string 1 on the diagram is the last one
+    pub strings: Vec<Pitch>
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning::standard()
+    }
+}
+
+impl Tuning {
+    fn preset(name: &str, notation: &str) -> Self {
+        Tuning {
+            name: name.to_owned(),
+            strings: Tuning::parse_notation(notation).unwrap()
+        }
+    }
+
+    pub fn standard() -> Self {
+        Tuning::preset("Standard", "E2 A2 D3 G3 B3 E4")
+    }
+
+    pub fn drop_d() -> Self {
+        Tuning::preset("Drop D", "D2 A2 D3 G3 B3 E4")
+    }
+
+    pub fn dadgad() -> Self {
+        Tuning::preset("DADGAD", "D2 A2 D3 G3 A3 D4")
+    }
+
+    pub fn open_g() -> Self {
+        Tuning::preset("Open G", "D2 G2 D3 G3 B3 D4")
+    }
+
+    pub fn half_step_down() -> Self {
+        Tuning::preset("Half step down", "Eb2 Ab2 Db3 Gb3 Bb3 Eb4")
+    }
+
+    pub fn seven_string() -> Self {
+        Tuning::preset("7-string standard", "B1 E2 A2 D3 G3 B3 E4")
+    }
+
+    pub fn eight_string() -> Self {
+        Tuning::preset("8-string standard", "F#1 B1 E2 A2 D3 G3 B3 E4")
+    }
+
+    pub fn bass() -> Self {
+        Tuning::preset("Bass standard", "E1 A1 D2 G2")
+    }
+
+    pub fn bass_drop_d() -> Self {
+        Tuning::preset("Bass drop D", "D1 A1 D2 G2")
+    }
+
+    // re-entrant, the G string is tuned above C
+    pub fn ukulele() -> Self {
+        Tuning::preset("Ukulele standard", "G4 C4 E4 A4")
+    }
+
+    pub fn ukulele_low_g() -> Self {
+        Tuning::preset("Ukulele low G", "G3 C4 E4 A4")
+    }
+
+    pub fn presets() -> Vec<Tuning> {
+        vec![
+            Tuning::standard(),
+            Tuning::drop_d(),
+            Tuning::dadgad(),
+            Tuning::open_g(),
+            Tuning::half_step_down(),
+            Tuning::seven_string(),
+            Tuning::eight_string(),
+            Tuning::bass(),
+            Tuning::bass_drop_d(),
+            Tuning::ukulele(),
+            Tuning::ukulele_low_g()
+        ]
+    }
+
+    // picks up the preset name if the strings match one, otherwise the tuning is custom
+    pub fn from_strings(strings: Vec<Pitch>) -> Self {
+        match Tuning::presets().into_iter().find(|t| t.strings == strings) {
+            Some(preset) => preset,
+            None => Tuning {
+                name: CUSTOM_TUNING_NAME.to_owned(),
+                strings
+            }
+        }
+    }
+
+    // "E2 A2 D3 G3 B3 E4", lowest string first
+    pub fn parse_notation(notation: &str) -> Result<Vec<Pitch>, ParsePitchError> {
+        notation.split_whitespace().map(|p| p.parse::<Pitch>()).collect()
+    }
+
+    pub fn notation(&self) -> String {
+        self.strings.iter().map(|p| p.to_string()).join(" ")
+    }
+
+    pub fn string_count(&self) -> i32 {
+        self.strings.len() as i32
+    }
+
+    // strings are numbered like on the diagram: 1 is the highest one
+    pub fn open_string(&self, string: i32) -> Option<Pitch> {
+        if string < 1 || string > self.string_count() {
+            None
+        } else {
+            Some(self.strings[(self.string_count() - string) as usize])
+        }
+    }
+
+    pub fn pitch_at(&self, note: NotePos) -> Option<Pitch> {
+        self.open_string(note.1).map(|open| open.transpose(note.0, false))
+    }
+}
+
+// preset picker plus a text field for custom tunings, returns true if the tuning was changed
+pub fn tuning_editor(ui: &mut Ui, id_source: impl Hash, tuning: &mut Tuning) -> bool {
+    let mut changed = false;
+    let id = ui.make_persistent_id(id_source);
+
+    let string_count = tuning.strings.len();
+
+    ui.horizontal(|ui| {
+        ComboBox::from_id_source(id)
+            .selected_text(&tuning.name)
+            .show_ui(ui, |ui| {
+                for preset in Tuning::presets().into_iter().filter(|t| t.strings.len() == string_count) {
+                    if ui.selectable_label(*tuning == preset, &preset.name).clicked() {
+                        *tuning = preset;
+                        changed = true;
+                    }
+                }
+            });
+
+        // keep whatever is being typed until it parses into a tuning with the same string count
+        let text_id = id.with("notation");
+        let mut text = ui.data_mut(|d| d.get_temp::<String>(text_id)).unwrap_or_else(|| tuning.notation());
+        let response = ui.add(TextEdit::singleline(&mut text).desired_width(150.0));
+        if response.changed() {
+            match Tuning::parse_notation(&text) {
+                Ok(strings) if strings.len() == tuning.strings.len() => {
+                    *tuning = Tuning::from_strings(strings);
+                    changed = true;
+                }
+                _ => ()
+            }
+        }
+        if response.has_focus() {
+            ui.data_mut(|d| d.insert_temp(text_id, text));
+        } else {
+            ui.data_mut(|d| d.remove::<String>(text_id));
+        }
+    });
+
+    changed
+}
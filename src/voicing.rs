@@ -0,0 +1,232 @@
+use std::collections::HashSet;
+use itertools::Itertools;
+use serde::{Serialize, Deserialize};
+use crate::symbol::ChordSymbol;
+use crate::chord::NotePos;
+use crate::instrument::Instrument;
+use crate::pitch::PitchClass;
+
+const MAX_FINGERS: usize = 4;
+const MAX_RESULTS: usize = 60;
+
+#[derive(Serialize, Deserialize)]
+pub struct VoicingFilter {
+    // widest distance between fretted notes, counted in frets like a hand span
+    pub max_stretch: i32,
+    pub max_muted: i32,
+    // strings the voicing may use, everything else stays muted
+    pub strings: Vec<i32>
+}
+
+impl Default for VoicingFilter {
+    fn default() -> Self {
+        VoicingFilter {
+            max_stretch: 4,
+            max_muted: 2,
+            strings: (1..=6).collect()
+        }
+    }
+}
+
+// generator inputs and results for the chords tab
+#[derive(Default, Serialize, Deserialize)]
+pub struct VoicingGenerator {
+    pub is_open: bool,
+    pub instrument: Instrument,
+    pub filter: VoicingFilter,
+    #[serde(skip)]
+    pub results: Vec<Vec<NotePos>>,
+    #[serde(skip)]
+    pub error: Option<String>
+}
+
+impl VoicingGenerator {
+    pub fn run(&mut self, chord_name: &str) {
+        self.results.clear();
+        self.error = None;
+        match ChordSymbol::parse(chord_name) {
+            Ok(symbol) => {
+                self.results = generate_voicings(&symbol, &self.instrument, &self.filter);
+                if self.results.is_empty() {
+                    self.error = Some("no playable voicings, try loosening the filters".to_owned());
+                }
+            }
+            Err(error) => self.error = Some(format!("can't read \"{}\": {}", chord_name, error))
+        }
+    }
+}
+
+// playable shapes for the chord across the neck, lowest positions first
+pub fn generate_voicings(symbol: &ChordSymbol, instrument: &Instrument, filter: &VoicingFilter) -> Vec<Vec<NotePos>> {
+    let allowed = symbol.tones();
+    let required = symbol.required_tones();
+    let strings = (1..=instrument.string_count()).filter(|s| filter.strings.contains(s)).collect::<Vec<_>>();
+    let stretch = filter.max_stretch.max(1);
+
+    let mut found: HashSet<Vec<NotePos>> = HashSet::new();
+    let mut voicings = vec![];
+    for window_start in 1..=instrument.frets {
+        let window = window_start..=(window_start + stretch - 1).min(instrument.frets);
+        // every string is either muted, open or fretted inside the window
+        let options = strings.iter().map(|string| {
+            let open = instrument.tuning.open_string(*string).unwrap().pitch_class();
+            let mut frets: Vec<Option<i32>> = vec![None];
+            frets.extend(std::iter::once(0).chain(window.clone())
+                .filter(|fret| allowed.contains(&open.transpose(*fret)))
+                .map(Some));
+            frets
+        }).collect::<Vec<_>>();
+
+        for choice in options.iter().multi_cartesian_product() {
+            let shape = strings.iter().zip(choice)
+                .filter_map(|(string, fret)| fret.map(|f| (f, *string)))
+                .collect::<Vec<NotePos>>();
+            if is_playable(&shape, symbol, instrument, filter, &required, strings.len()) && found.insert(shape.clone()) {
+                voicings.push(shape);
+            }
+        }
+    }
+
+    voicings.into_iter()
+        .sorted_by_key(|shape| (position(shape), strings.len() - shape.len(), fingers(shape)))
+        .take(MAX_RESULTS)
+        .collect()
+}
+
+// the easiest shape sounding exactly these midi notes, for chords played on a midi instrument
+pub fn fit_shape(midi_notes: &[i32], instrument: &Instrument) -> Result<Vec<NotePos>, String> {
+    let notes = midi_notes.iter().copied().sorted().dedup().collect::<Vec<_>>();
+    if notes.len() > instrument.string_count() as usize {
+        return Err(format!("{} notes don't fit on {} strings", notes.len(), instrument.string_count()));
+    }
+    let mut shapes = vec![];
+    place_notes(&notes, instrument, &mut vec![], &mut shapes);
+    shapes.into_iter()
+        .min_by_key(|shape| (fingers(shape) > MAX_FINGERS, span(shape), muted_inside(shape), position(shape), fingers(shape)))
+        .map(|shape| shape.into_iter().sorted_by_key(|(_, string)| -string).collect())
+        .ok_or_else(|| "the notes can't be played together in this tuning".to_owned())
+}
+
+// every way of putting the notes on different strings inside the neck
+fn place_notes(notes: &[i32], instrument: &Instrument, placed: &mut Vec<NotePos>, shapes: &mut Vec<Vec<NotePos>>) {
+    let Some((note, rest)) = notes.split_first() else {
+        shapes.push(placed.clone());
+        return;
+    };
+    for string in 1..=instrument.string_count() {
+        if placed.iter().any(|(_, s)| *s == string) {
+            continue;
+        }
+        let fret = note - instrument.tuning.open_string(string).unwrap().midi();
+        if (0..=instrument.frets).contains(&fret) {
+            placed.push((fret, string));
+            place_notes(rest, instrument, placed, shapes);
+            placed.pop();
+        }
+    }
+}
+
+fn is_playable(shape: &[NotePos], symbol: &ChordSymbol, instrument: &Instrument, filter: &VoicingFilter, required: &[PitchClass], string_count: usize) -> bool {
+    if shape.len() < 2 || string_count - shape.len() > filter.max_muted as usize {
+        return false;
+    }
+
+    let pitches = shape.iter()
+        .filter_map(|note| instrument.tuning.pitch_at(*note))
+        .sorted_by_key(|p| p.midi())
+        .collect::<Vec<_>>();
+    let pitch_classes = pitches.iter().map(|p| p.pitch_class()).collect::<Vec<_>>();
+    let lowest_is_bass = pitches.first().map(|p| p.pitch_class()) == Some(symbol.bass_tone());
+
+    lowest_is_bass
+        && required.iter().all(|tone| pitch_classes.contains(tone))
+        && span(shape) < filter.max_stretch
+        && fingers(shape) <= MAX_FINGERS
+}
+
+// distance between the lowest and the highest fretted note
+fn span(shape: &[NotePos]) -> i32 {
+    let fretted = shape.iter().map(|(fret, _)| *fret).filter(|fret| *fret > 0);
+    match fretted.minmax().into_option() {
+        Some((min, max)) => max - min,
+        None => 0
+    }
+}
+
+// muted strings between the lowest and the highest played one
+fn muted_inside(shape: &[NotePos]) -> i32 {
+    match shape.iter().map(|(_, string)| *string).minmax().into_option() {
+        Some((low, high)) => high - low + 1 - shape.len() as i32,
+        None => 0
+    }
+}
+
+// lowest fretted position, open shapes count as the first one
+fn position(shape: &[NotePos]) -> i32 {
+    shape.iter().map(|(fret, _)| *fret).filter(|fret| *fret > 0).min().unwrap_or(1)
+}
+
+// fingers needed, notes on the lowest fret are taken as a barre
+fn fingers(shape: &[NotePos]) -> usize {
+    let fretted = shape.iter().filter(|(fret, _)| *fret > 0).count();
+    let lowest = position(shape);
+    let barred = shape.iter().filter(|(fret, _)| *fret == lowest).count();
+    if barred > 1 {
+        fretted - barred + 1
+    } else {
+        fretted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voicings(name: &str, instrument: &Instrument, filter: &VoicingFilter) -> Vec<Vec<NotePos>> {
+        generate_voicings(&ChordSymbol::parse(name).unwrap(), instrument, filter)
+    }
+
+    #[test]
+    fn shapes_stay_inside_the_stretch() {
+        let guitar = Instrument::guitar();
+        let wide = voicings("C", &guitar, &VoicingFilter::default());
+        assert!(wide.contains(&vec![(0, 1), (1, 2), (0, 3), (2, 4), (3, 5)]));
+        assert!(wide.iter().all(|shape| span(shape) < 4));
+
+        let narrow = voicings("C", &guitar, &VoicingFilter { max_stretch: 2, ..VoicingFilter::default() });
+        assert!(!narrow.is_empty());
+        assert!(narrow.iter().all(|shape| span(shape) < 2));
+        assert!(!narrow.contains(&vec![(0, 1), (1, 2), (0, 3), (2, 4), (3, 5)]));
+    }
+
+    #[test]
+    fn muted_and_allowed_strings() {
+        let guitar = Instrument::guitar();
+        let full = voicings("G", &guitar, &VoicingFilter { max_muted: 0, ..VoicingFilter::default() });
+        assert!(!full.is_empty());
+        assert!(full.iter().all(|shape| shape.len() == 6));
+
+        let top = voicings("G", &guitar, &VoicingFilter { strings: vec![1, 2, 3, 4], ..VoicingFilter::default() });
+        assert!(!top.is_empty());
+        assert!(top.iter().all(|shape| shape.iter().all(|(_, string)| *string <= 4)));
+        // the lowest note is the bass of a slash chord
+        let tuning = &guitar.tuning;
+        for shape in voicings("C/E", &guitar, &VoicingFilter::default()) {
+            let lowest = shape.iter().filter_map(|note| tuning.pitch_at(*note)).min_by_key(|p| p.midi()).unwrap();
+            assert_eq!(lowest.pitch_class().semitone(), 4);
+        }
+    }
+
+    #[test]
+    fn shapes_fit_the_instrument() {
+        let ukulele = Instrument::ukulele();
+        let shapes = voicings("C", &ukulele, &VoicingFilter::default());
+        assert!(shapes.contains(&vec![(3, 1), (0, 2), (0, 3), (0, 4)]));
+        assert!(shapes.iter().all(|shape| shape.iter().all(|note| ukulele.contains(*note))));
+
+        let short = Instrument { frets: 5, ..Instrument::guitar() };
+        let shapes = voicings("D", &short, &VoicingFilter::default());
+        assert!(!shapes.is_empty());
+        assert!(shapes.iter().all(|shape| shape.iter().all(|(fret, _)| *fret <= 5)));
+    }
+}